@@ -0,0 +1,159 @@
+// renders the OpenAPI document of the server served at /openapi.json,
+// a minimal replacement for Swagger UI so the docs page works without any external script
+
+// creates an element with the given class and text
+function element(tag, className, text) {
+    const node = document.createElement(tag);
+
+    if (className) {
+        node.className = className;
+    }
+    if (text !== undefined && text !== null) {
+        node.textContent = text;
+    }
+    return node;
+}
+
+// follows a local `$ref` such as `#/components/parameters/format`
+function resolve(doc, value) {
+    if (!value || !value.$ref) {
+        return value;
+    }
+
+    return value.$ref
+        .replace(/^#\//, '')
+        .split('/')
+        .reduce((node, key) => node && node[key], doc);
+}
+
+// a short description of a schema: its type, default and range
+function describeSchema(doc, schema) {
+    schema = resolve(doc, schema) || {};
+
+    let text = schema.type || (schema.$ref ? schema.$ref.split('/').pop() : 'object');
+    if (schema.format) {
+        text += ` (${schema.format})`;
+    }
+    if (schema.enum) {
+        text += `: ${schema.enum.join(', ')}`;
+    }
+    if (schema.minimum !== undefined || schema.maximum !== undefined) {
+        text += `, ${schema.minimum ?? ''} to ${schema.maximum ?? ''}`;
+    }
+    if (schema.default !== undefined && schema.default !== null) {
+        text += `, defaults to ${schema.default}`;
+    }
+    return text;
+}
+
+// a table with a header row and a row per entry
+function table(headers, rows) {
+    const node = element('table');
+    const head = element('tr');
+
+    headers.forEach(header => head.appendChild(element('th', null, header)));
+    node.appendChild(head);
+
+    rows.forEach(row => {
+        const tr = element('tr');
+        row.forEach(cell => tr.appendChild(element('td', null, cell)));
+        node.appendChild(tr);
+    });
+    return node;
+}
+
+// renders a single operation as a collapsible block
+function renderOperation(doc, path, method, operation) {
+    const details = element('details');
+    const summary = element('summary');
+
+    summary.appendChild(element('span', `method ${method}`, method));
+    summary.appendChild(element('span', 'path', path));
+    summary.appendChild(element('span', 'summary', operation.summary));
+    details.appendChild(summary);
+
+    const body = element('div', 'operation');
+    if (operation.description) {
+        body.appendChild(element('p', null, operation.description));
+    }
+
+    const parameters = (operation.parameters || []).map(parameter => resolve(doc, parameter));
+    if (parameters.length > 0) {
+        body.appendChild(element('h4', null, 'Parameters'));
+        body.appendChild(table(
+            ['Name', 'In', 'Schema', 'Description'],
+            parameters.map(parameter => [
+                parameter.name,
+                parameter.in,
+                describeSchema(doc, parameter.schema),
+                parameter.description || '',
+            ]),
+        ));
+    }
+
+    const content = operation.requestBody && operation.requestBody.content;
+    if (content) {
+        Object.entries(content).forEach(([type, media]) => {
+            const schema = resolve(doc, media.schema) || {};
+            const required = schema.required || [];
+
+            body.appendChild(element('h4', null, `Body (${type})`));
+            body.appendChild(table(
+                ['Field', 'Schema', 'Description'],
+                Object.entries(schema.properties || {}).map(([name, property]) => [
+                    required.includes(name) ? `${name} (required)` : name,
+                    describeSchema(doc, property),
+                    property.description || '',
+                ]),
+            ));
+        });
+    }
+
+    body.appendChild(element('h4', null, 'Responses'));
+    body.appendChild(table(
+        ['Status', 'Description'],
+        Object.entries(operation.responses || {}).map(([status, response]) => [
+            status,
+            (resolve(doc, response) || {}).description || '',
+        ]),
+    ));
+
+    details.appendChild(body);
+    return details;
+}
+
+async function main() {
+    const container = document.getElementById('operations');
+    let doc;
+
+    try {
+        const response = await fetch('/openapi.json');
+        doc = await response.json();
+    } catch (error) {
+        container.appendChild(element('p', null, `Failed to load the API document: ${error}`));
+        return;
+    }
+
+    document.getElementById('title').textContent = `${doc.info.title} ${doc.info.version}`;
+    document.getElementById('description').textContent = doc.info.description;
+
+    // operations grouped by their first tag, in the order the tags first appear
+    const groups = new Map();
+    Object.entries(doc.paths).forEach(([path, methods]) => {
+        Object.entries(methods).forEach(([method, operation]) => {
+            const tag = (operation.tags && operation.tags[0]) || 'other';
+
+            if (!groups.has(tag)) {
+                groups.set(tag, []);
+            }
+            groups.get(tag).push(renderOperation(doc, path, method, operation));
+        });
+    });
+
+    groups.forEach((operations, tag) => {
+        container.appendChild(element('h2', null, tag));
+        operations.forEach(operation => container.appendChild(operation));
+    });
+}
+
+window.addEventListener('load', main);
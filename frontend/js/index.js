@@ -138,6 +138,20 @@ async function formHandler(event) {
     }
 }
 
+function showOutput(url) {
+    const output = document.getElementById('output-container');
+    output.innerHTML =
+        `<img class="lg-shadow" id="output-image" src="${url}" alt="output image"/>`;
+}
+
+function showError(message) {
+    const output = document.getElementById('output-container');
+    alert(message);
+    output.innerHTML =
+        `<div class="lg-shadow" id="output-placeholder">Output Image</div>`;
+}
+
+// submits the image as a job and follows its progress until the result is ready
 async function makeRequest(endpoint, bytes) {
     const output = document.getElementById('output-container');
     output.innerHTML =
@@ -152,18 +166,42 @@ async function makeRequest(endpoint, bytes) {
     };
 
     let params = new URLSearchParams(query);
-    let response = await fetch(`/${endpoint}?` + params, payload);
-    if (response.ok) {
-        const url = URL.createObjectURL(
-            await response.blob()
-        );
-        output.innerHTML =
-            `<img class="lg-shadow" id="output-image" src="${url}" alt="output image"/>`;
-    } else {
-        alert(`${response.status}: Something went wrong`);
-        output.innerHTML =
-            `<div class="lg-shadow" id="output-placeholder">Output Image</div>`;
+    let response = await fetch(`/jobs/${endpoint}?` + params, payload);
+    if (!response.ok) {
+        return showError(`${response.status}: Something went wrong`);
     }
+
+    const job = await response.json();
+    const events = new EventSource(`${job.location}/events`);
+
+    events.addEventListener('progress', (event) => {
+        const { stage, percent } = JSON.parse(event.data);
+        const processing = document.getElementById('processing');
+
+        if (processing) {
+            processing.textContent = stage === 'processing'
+                ? `Processing image... ${percent}%`
+                : `${stage.charAt(0).toUpperCase() + stage.slice(1)} image...`;
+        }
+    });
+
+    events.addEventListener('done', async (event) => {
+        events.close();
+        const { result } = JSON.parse(event.data);
+        const image = await fetch(result);
+
+        if (image.ok) {
+            showOutput(URL.createObjectURL(await image.blob()));
+        } else {
+            showError(`${image.status}: Something went wrong`);
+        }
+    });
+
+    events.addEventListener('failed', (event) => {
+        events.close();
+        const { message } = JSON.parse(event.data);
+        showError(message);
+    });
 }
 
 function modalFormHandler(event) {
@@ -184,7 +222,11 @@ function modalFormHandler(event) {
             let value = input.type === 'checkbox'
                 ? input.checked
                 : input.value;
-            query[input.id] = value;
+
+            // optional options without a default are left out when empty
+            if (value !== '') {
+                query[input.id] = value;
+            }
         }
     }
 }
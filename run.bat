@@ -1,4 +1,4 @@
 @ECHO off
 
 :: just for quick local testing
-cargo run --release
\ No newline at end of file
+cargo run --release --bin image-web --features server
\ No newline at end of file
@@ -0,0 +1,336 @@
+//! module loading the assets used by the effects (fonts, the lego brick and the minecraft blocks)
+//! into an [`Assets`] handle, which is passed explicitly to the effects that need it.
+//!
+//! assets are read from a [`Source`], either a directory
+//! or, when compiled with the `embed` feature, the copy of `./assets` built into the executable.
+//!
+//! an asset that fails to load does not fail the others,
+//! only the effects using it are unavailable, see [`Assets::check`]
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fs,
+    path::{Path, PathBuf},
+};
+use ril::prelude::*;
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+
+/// name of every asset, its path relative to the assets directory
+pub const NAMES: [&str; 5] = [
+    LEGO,
+    UNICODE_FONT,
+    MONOSPACE_FONT,
+    CODE_FONT,
+    MINECRAFT,
+];
+
+/// gray lego brick, used by `lego`
+pub const LEGO: &str = "lego.png";
+/// unicode font (supports braille glyphs), used by `braille`
+pub const UNICODE_FONT: &str = "unicode.ttf";
+/// monospace font (consolas, equal in spacing), used by `ascii`
+pub const MONOSPACE_FONT: &str = "monospace.ttf";
+/// "programming / code" font, used by `matrix`
+pub const CODE_FONT: &str = "monaco-linux.ttf";
+/// directory of minecraft blocks, used by `minecraft`
+pub const MINECRAFT: &str = "minecraft";
+
+/// size the fonts are loaded at
+const FONT_SIZE: f32 = 30.0;
+
+/// why an asset could not be loaded
+#[derive(Debug, Clone)]
+pub enum AssetError {
+    /// the file or directory does not exist
+    Missing(PathBuf),
+    /// the file exists but could not be read or decoded
+    Invalid {
+        path: PathBuf,
+        reason: String,
+    },
+    /// the directory holds no file that could be loaded
+    Empty(PathBuf),
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing(path) => write!(
+                f,
+                "{} does not exist, check that `server.assets` (`ASSETS_DIR`) points to the assets directory",
+                path.display(),
+            ),
+            Self::Invalid { path, reason } => write!(f, "{} could not be loaded: {reason}", path.display()),
+            Self::Empty(path) => write!(f, "{} holds no asset that could be loaded", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+/// shortcut typealias for an asset, which may have failed to load
+pub type AssetResult<T> = std::result::Result<T, AssetError>;
+
+/// the copy of `./assets` built into the executable
+#[cfg(feature = "embed")]
+static EMBEDDED: include_dir::Dir<'static> = include_dir::include_dir!("$CARGO_MANIFEST_DIR/assets");
+
+/// where the assets are read from
+#[derive(Debug, Clone)]
+pub enum Source {
+    /// a directory on the filesystem
+    Dir(PathBuf),
+    /// the copy of `./assets` built into the executable
+    #[cfg(feature = "embed")]
+    Embedded,
+}
+
+impl Source {
+    /// the directory `dir` if set, otherwise the embedded assets
+    /// when compiled with the `embed` feature, otherwise `./assets`
+    pub fn new(dir: Option<&Path>) -> Self {
+        match dir {
+            Some(dir) => Self::Dir(dir.to_path_buf()),
+            #[cfg(feature = "embed")]
+            None => Self::Embedded,
+            #[cfg(not(feature = "embed"))]
+            None => Self::Dir(PathBuf::from("./assets")),
+        }
+    }
+
+    /// the path of `asset`, as shown in errors
+    fn path(&self, asset: &str) -> PathBuf {
+        match self {
+            Self::Dir(dir) => dir.join(asset),
+            #[cfg(feature = "embed")]
+            Self::Embedded => Path::new("<embedded>").join(asset),
+        }
+    }
+
+    /// the contents of the file `asset`
+    fn read(&self, asset: &str) -> AssetResult<Cow<'static, [u8]>> {
+        let path = self.path(asset);
+
+        match self {
+            Self::Dir(_) if !path.is_file() => Err(AssetError::Missing(path)),
+            Self::Dir(_) => fs::read(&path)
+                .map(Cow::Owned)
+                .map_err(|err| AssetError::Invalid {
+                    reason: err.to_string(),
+                    path,
+                }),
+            #[cfg(feature = "embed")]
+            Self::Embedded => EMBEDDED.get_file(asset)
+                .map(|file| Cow::Borrowed(file.contents()))
+                .ok_or(AssetError::Missing(path)),
+        }
+    }
+
+    /// the name of every file of the directory `asset`
+    fn files(&self, asset: &str) -> AssetResult<Vec<String>> {
+        let path = self.path(asset);
+
+        match self {
+            Self::Dir(_) if !path.is_dir() => Err(AssetError::Missing(path)),
+            Self::Dir(_) => fs::read_dir(&path)
+                .and_then(|entries| entries
+                    .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+                    .collect()
+                )
+                .map_err(|err| AssetError::Invalid {
+                    reason: err.to_string(),
+                    path,
+                }),
+            #[cfg(feature = "embed")]
+            Self::Embedded => EMBEDDED.get_dir(asset)
+                .map(|dir| dir.files()
+                    .filter_map(|file| file.path().file_name())
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .collect()
+                )
+                .ok_or(AssetError::Missing(path)),
+        }
+    }
+}
+
+/// whether an asset has been loaded yet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum AssetStatus {
+    /// not loaded yet
+    Pending,
+    /// loaded successfully
+    Loaded,
+    /// could not be loaded
+    Failed {
+        error: String,
+    },
+}
+
+/// the minecraft blocks, keyed on their average color
+pub struct Minecraft {
+    /// every block, resized to the block size
+    pub blocks: HashMap<(u8, u8, u8, u8), Image<Rgba>>,
+    /// the average color of every block
+    pub palette: Vec<(u8, u8, u8, u8)>,
+    /// amount of files of the directory that could not be loaded
+    pub failed: usize,
+}
+
+/// every asset used by the effects, see [`NAMES`]
+pub struct Assets {
+    pub lego: AssetResult<Image<Rgb>>,
+    pub unicode_font: AssetResult<Font>,
+    pub monospace_font: AssetResult<Font>,
+    pub code_font: AssetResult<Font>,
+    pub minecraft: AssetResult<Minecraft>,
+}
+
+/// decodes the file `asset` of `source` with `decode`,
+/// failing with [`AssetError::Missing`] if it does not exist
+pub fn load<T, E: fmt::Display>(
+    source: &Source,
+    asset: &str,
+    decode: impl FnOnce(&[u8]) -> std::result::Result<T, E>,
+) -> AssetResult<T> {
+    let bytes = source.read(asset)?;
+
+    decode(&bytes).map_err(|err| AssetError::Invalid {
+        reason: err.to_string(),
+        path: source.path(asset),
+    })
+}
+
+/// loads every minecraft block of `source`, resized to `block_size`,
+/// keyed on its average color
+pub fn load_minecraft(source: &Source, block_size: u32) -> AssetResult<Minecraft> {
+    let mut failed = 0;
+    let mut blocks = HashMap::new();
+
+    for name in source.files(MINECRAFT)? {
+        if !Path::new(&name)
+            .extension()
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("png"))
+        {
+            continue;
+        }
+
+        #[allow(clippy::option_if_let_else)]
+        if let Ok(block) =
+            load(source, &format!("{MINECRAFT}/{name}"), Image::<Rgba>::from_bytes_inferred)
+        {
+            let single = block.clone()
+                .resized(1, 1, ResizeAlgorithm::Bilinear);
+            blocks.insert(
+                single.pixel(0, 0).as_rgba_tuple(),
+                block.resized(block_size, block_size, ResizeAlgorithm::Bilinear),
+            );
+        } else {
+            failed += 1;
+        }
+    }
+
+    tracing::info!(loaded = blocks.len(), failed, "loaded minecraft blocks");
+    if blocks.is_empty() {
+        return Err(AssetError::Empty(source.path(MINECRAFT)));
+    }
+
+    Ok(Minecraft {
+        palette: blocks.keys().copied().collect(),
+        blocks,
+        failed,
+    })
+}
+
+/// logs the result of loading `asset`, passing it through
+fn logged<T>(asset: &str, result: AssetResult<T>) -> AssetResult<T> {
+    match &result {
+        Ok(_) => tracing::info!(asset, "loaded asset"),
+        Err(err) => tracing::error!(asset, %err, "failed to load asset"),
+    }
+    result
+}
+
+/// the asset named `name`, failing with [`Error::Unavailable`] if it could not be loaded
+fn available<'a, T>(name: &'static str, asset: &'a AssetResult<T>) -> Result<&'a T> {
+    asset.as_ref()
+        .map_err(|err| Error::Unavailable {
+            asset: name,
+            reason: err.to_string(),
+        })
+}
+
+impl Assets {
+    /// loads every asset from `source`, with the minecraft blocks resized to `block_size`.
+    ///
+    /// may read from the disk, so it should not be called on the async runtime
+    pub fn load(source: &Source, block_size: u32) -> Self {
+        Self {
+            lego: logged(LEGO, load(source, LEGO, Image::from_bytes_inferred)),
+            unicode_font: logged(UNICODE_FONT, load(source, UNICODE_FONT, |bytes| Font::from_bytes(bytes, FONT_SIZE))),
+            monospace_font: logged(MONOSPACE_FONT, load(source, MONOSPACE_FONT, |bytes| Font::from_bytes(bytes, FONT_SIZE))),
+            code_font: logged(CODE_FONT, load(source, CODE_FONT, |bytes| Font::from_bytes(bytes, FONT_SIZE))),
+            minecraft: logged(MINECRAFT, load_minecraft(source, block_size)),
+        }
+    }
+
+    /// the error of the asset named `name`, if it failed to load
+    fn error(&self, name: &str) -> Option<&AssetError> {
+        match name {
+            LEGO => self.lego.as_ref().err(),
+            UNICODE_FONT => self.unicode_font.as_ref().err(),
+            MONOSPACE_FONT => self.monospace_font.as_ref().err(),
+            CODE_FONT => self.code_font.as_ref().err(),
+            MINECRAFT => self.minecraft.as_ref().err(),
+            _ => None,
+        }
+    }
+
+    pub fn lego(&self) -> Result<&Image<Rgb>> {
+        available(LEGO, &self.lego)
+    }
+
+    pub fn unicode_font(&self) -> Result<&Font> {
+        available(UNICODE_FONT, &self.unicode_font)
+    }
+
+    pub fn monospace_font(&self) -> Result<&Font> {
+        available(MONOSPACE_FONT, &self.monospace_font)
+    }
+
+    pub fn code_font(&self) -> Result<&Font> {
+        available(CODE_FONT, &self.code_font)
+    }
+
+    pub fn minecraft(&self) -> Result<&Minecraft> {
+        available(MINECRAFT, &self.minecraft)
+    }
+
+    /// fails with [`Error::Unavailable`] if any of the assets named `names` failed to load
+    pub fn check(&self, names: &[&'static str]) -> Result<()> {
+        for name in names {
+            if let Some(err) = self.error(name) {
+                return Err(Error::Unavailable {
+                    asset: name,
+                    reason: err.to_string(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// the status of every asset, keyed on its name
+    pub fn status(&self) -> BTreeMap<&'static str, AssetStatus> {
+        NAMES.into_iter()
+            .map(|name| (name, match self.error(name) {
+                Some(err) => AssetStatus::Failed { error: err.to_string() },
+                None => AssetStatus::Loaded,
+            }))
+            .collect()
+    }
+}
@@ -0,0 +1,423 @@
+//! command line tool applying any effect to local files, without running the server.
+//!
+//! every effect of the [`REGISTRY`] is a subcommand taking its options as flags,
+//! for example `image-cli lego --size 30 photos/ -o lego/`
+
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+use clap::{builder::RangedU64ValueParser, value_parser, Arg, ArgAction, ArgMatches, Command};
+use image_web::{
+    assets::{Assets, Source},
+    config::{self, EffectsConfig},
+    context::Context,
+    effect::{DynEffect, Parameter, ParameterType},
+    effects::REGISTRY,
+    error::Error,
+    output::{Output, OutputFormat},
+};
+use ril::prelude::*;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+/// extensions of the files picked up from input directories
+const EXTENSIONS: [&str; 7] = ["png", "apng", "jpg", "jpeg", "gif", "webp", "bmp"];
+
+/// the parts of the config file of the server used by the tool, every other key is ignored
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    server: ServerConfig,
+    effects: EffectsConfig,
+}
+
+/// the `[server]` table of the config file
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ServerConfig {
+    /// directory containing the effect assets, `ASSETS_DIR`
+    assets: Option<PathBuf>,
+}
+
+impl FileConfig {
+    /// reads the config file of the server found by [`config::path`], if any,
+    /// with the `ASSETS_DIR` override, and validates its `[effects]`
+    fn load() -> Result<Self, String> {
+        let mut file = match config::path() {
+            Some(path) => {
+                let contents = fs::read_to_string(&path)
+                    .map_err(|err| format!("failed to read {}: {err}", path.display()))?;
+
+                toml::from_str::<Self>(&contents)
+                    .map_err(|err| format!("failed to parse {}: {err}", path.display()))?
+            }
+            None => Self::default(),
+        };
+
+        if let Ok(dir) = std::env::var("ASSETS_DIR") {
+            file.server.assets = Some(PathBuf::from(dir));
+        }
+
+        let errors = file.effects.validate();
+        if errors.is_empty() {
+            Ok(file)
+        } else {
+            Err(errors.join(", "))
+        }
+    }
+}
+
+/// parses an [`OutputFormat`] the same way the `format` query parameter is,
+/// failing for the formats that were not compiled in
+fn parse_format(format: &str) -> Result<OutputFormat, String> {
+    serde_json::from_value::<OutputFormat>(json!(format.to_ascii_lowercase()))
+        .ok()
+        .filter(|format| format.is_enabled())
+        .ok_or_else(|| format!("unknown format {format}"))
+}
+
+/// the flag of a single option of an effect
+fn option_arg(parameter: &Parameter) -> Arg {
+    let help = if parameter.default.is_null() {
+        parameter.description.to_string()
+    } else {
+        format!("{} [default: {}]", parameter.description, parameter.default)
+    };
+    let arg = Arg::new(parameter.name)
+        .long(parameter.name)
+        .help(help);
+
+    match parameter.kind {
+        ParameterType::Integer | ParameterType::Number => {
+            let parameter = parameter.clone();
+            arg.value_parser(move |raw: &str| parameter.parse(raw).map_err(|err| err.message))
+        }
+        // `--invert` alone means `--invert=true`
+        ParameterType::Boolean => arg
+            .value_parser(value_parser!(bool))
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("true"),
+    }
+}
+
+/// the arguments shared by every subcommand
+fn common_args() -> [Arg; 6] {
+    [
+        Arg::new("inputs")
+            .help("Image files, directories or glob patterns to process")
+            .required(true)
+            .num_args(1..),
+        Arg::new("output")
+            .short('o')
+            .long("output")
+            .value_parser(value_parser!(PathBuf))
+            .help("Output file for a single input, otherwise the directory to write into [default: .]"),
+        Arg::new("format")
+            .short('f')
+            .long("format")
+            .value_parser(parse_format)
+            .help(format!(
+                "Output format ({}), inferred from the output file otherwise",
+                OutputFormat::enabled()
+                    .map(OutputFormat::extension)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )),
+        Arg::new("quality")
+            .short('q')
+            .long("quality")
+            .value_parser(value_parser!(u8).range(1..=100))
+            .help("Quality for lossy formats [default: 90]"),
+        Arg::new("first")
+            .long("first-frame")
+            .action(ArgAction::SetTrue)
+            .help("Only process the first frame of animated inputs"),
+        Arg::new("jobs")
+            .short('j')
+            .long("jobs")
+            .value_parser(RangedU64ValueParser::<usize>::new().range(1..))
+            .help("Amount of files processed at once [default: amount of cpus]"),
+    ]
+}
+
+/// the command line, with one subcommand per effect
+/// whose flags have the defaults and ranges of `effects`
+fn command(effects: &EffectsConfig) -> Command {
+    REGISTRY.iter()
+        .map(|effect| effect.info(effects))
+        .fold(
+            Command::new("image-cli")
+                .about("Applies the effects of the imaging app to local files")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .arg(
+                    Arg::new("assets")
+                        .long("assets")
+                        .global(true)
+                        .value_parser(value_parser!(PathBuf))
+                        .help("Directory containing the effect assets [default: `server.assets` of the config, or the embedded assets]"),
+                ),
+            |command, info| command.subcommand(
+                Command::new(info.name)
+                    .about(info.description)
+                    .args(info.options.iter().map(option_arg))
+                    .args(common_args()),
+            ),
+        )
+}
+
+/// the options of the effect given as flags, as the JSON its options deserialize from
+fn options(effect: &dyn DynEffect, matches: &ArgMatches, effects: &EffectsConfig) -> Value {
+    let mut options = Map::new();
+
+    for parameter in effect.info(effects).options {
+        let value = match parameter.kind {
+            ParameterType::Integer | ParameterType::Number => matches.get_one::<Value>(parameter.name).cloned(),
+            ParameterType::Boolean => matches.get_one::<bool>(parameter.name).map(|value| json!(value)),
+        };
+
+        if let Some(value) = value {
+            options.insert(parameter.name.to_string(), value);
+        }
+    }
+
+    Value::Object(options)
+}
+
+/// every file matched by `inputs`: files as is, the images inside directories and glob patterns
+fn expand(inputs: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+
+    for input in inputs {
+        let path = Path::new(input);
+
+        if path.is_dir() {
+            let mut images = fs::read_dir(path)
+                .map_err(|err| format!("{input}: {err}"))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_file() && path.extension()
+                    .and_then(OsStr::to_str)
+                    .map_or(false, |ext| EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+                )
+                .collect::<Vec<_>>();
+
+            images.sort();
+            files.extend(images);
+        } else if path.exists() {
+            files.push(path.to_path_buf());
+        } else {
+            let matched = glob::glob(input)
+                .map_err(|err| format!("{input}: {err}"))?
+                .filter_map(Result::ok)
+                .filter(|path| path.is_file())
+                .collect::<Vec<_>>();
+
+            if matched.is_empty() {
+                return Err(format!("{input}: no such file, directory or matching pattern"));
+            }
+            files.extend(matched);
+        }
+    }
+
+    Ok(files)
+}
+
+/// the name of the output of `input` in the output directory, without its extension,
+/// which depends on whether the output is animated
+fn output_name(input: &Path, effect: &dyn DynEffect) -> String {
+    format!(
+        "{}_{}",
+        input.file_stem().and_then(OsStr::to_str).unwrap_or("output"),
+        effect.name(),
+    )
+}
+
+/// fails with the first two `files` that would be written to the same output,
+/// such as `a/x.png` and `b/x.png` or `x.png` and `x.gif`
+fn check_collisions(files: &[PathBuf], effect: &dyn DynEffect) -> Result<(), String> {
+    let mut names = HashMap::new();
+
+    for file in files {
+        if let Some(other) = names.insert(output_name(file, effect), file) {
+            return Err(format!(
+                "{} and {} would both be written as {}, rename one of them or process them separately",
+                other.display(),
+                file.display(),
+                output_name(file, effect),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// where and how a single file is processed
+struct Job<'a> {
+    effect: &'a dyn DynEffect,
+    options: Value,
+    assets: &'a Assets,
+    effects: &'a EffectsConfig,
+    /// the output file, if there is a single input
+    file: Option<PathBuf>,
+    /// the directory outputs are written to otherwise
+    dir: PathBuf,
+    format: Option<OutputFormat>,
+    quality: Option<u8>,
+    per_frame: bool,
+}
+
+impl Job<'_> {
+    /// applies the effect to `input` and writes the output, returning where it was written
+    fn run(&self, input: &Path) -> Result<PathBuf, String> {
+        let bytes = fs::read(input)
+            .map_err(|err| err.to_string())?;
+        let sequence = ImageSequence::<Rgba>::from_bytes_inferred(&bytes)
+            .and_then(|sequence| sequence.into_sequence())
+            .map_err(|err| Error::CorruptImage(err.to_string()).to_string())?;
+
+        let output = Output::map_frames(
+            sequence,
+            self.per_frame,
+            u64::MAX,
+            |image| Ok::<_, Error>(Output::Static(image)),
+        )
+            .and_then(|output| self.effect.apply_json(output, self.options.clone(), self.assets, self.effects, &Context::new()))
+            .map_err(|err| err.to_string())?;
+
+        let animated = output.is_animated();
+        let format = OutputFormat::negotiate(self.format, None, animated)
+            .ok_or_else(|| format!(
+                "the {} output cannot be encoded in the requested format",
+                if animated { "animated" } else { "static" },
+            ))?;
+        let encoded = output.encode(format, self.quality)
+            .map_err(|err| err.to_string())?;
+
+        let path = self.file.clone().unwrap_or_else(|| self.dir.join(format!(
+            "{}.{}",
+            output_name(input, self.effect),
+            format.extension(),
+        )));
+        fs::write(&path, encoded)
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+
+        Ok(path)
+    }
+}
+
+fn main() -> ExitCode {
+    // before building the command, so that the defaults and ranges of the flags are the configured ones
+    let config = match FileConfig::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Invalid configuration: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let matches = command(&config.effects).get_matches();
+    let Some((name, matches)) = matches.subcommand() else {
+        return ExitCode::FAILURE;
+    };
+
+    let effect = REGISTRY.get(name)
+        .expect("every subcommand is an effect");
+    let inputs = matches.get_many::<String>("inputs")
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+    let files = match expand(&inputs) {
+        Ok(files) => files,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let output = matches.get_one::<PathBuf>("output");
+    // a single input is written to the output itself, unless it is a directory
+    let file = output
+        .filter(|output| files.len() == 1 && !output.is_dir())
+        .cloned();
+    let dir = output.filter(|_| file.is_none())
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from("."));
+    // the workers would silently overwrite each other
+    if file.is_none() {
+        if let Err(err) = check_collisions(&files, effect.as_ref()) {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    }
+    if let Err(err) = fs::create_dir_all(&dir) {
+        eprintln!("{}: {err}", dir.display());
+        return ExitCode::FAILURE;
+    }
+
+    let format = matches.get_one::<OutputFormat>("format")
+        .copied()
+        .or_else(|| file.as_ref()
+            .and_then(|file| file.extension())
+            .and_then(OsStr::to_str)
+            .and_then(|ext| parse_format(ext).ok())
+        );
+
+    let assets = Assets::load(
+        &Source::new(matches.get_one::<PathBuf>("assets")
+            .or(config.server.assets.as_ref())
+            .map(PathBuf::as_path)
+        ),
+        config.effects.minecraft_block_size,
+    );
+    if let Err(err) = assets.check(effect.assets()) {
+        eprintln!("{err}");
+        return ExitCode::FAILURE;
+    }
+
+    let job = Job {
+        effect: effect.as_ref(),
+        options: options(effect.as_ref(), matches, &config.effects),
+        assets: &assets,
+        effects: &config.effects,
+        file,
+        dir,
+        format,
+        quality: matches.get_one::<u8>("quality").copied(),
+        per_frame: !matches.get_flag("first"),
+    };
+    let workers = matches.get_one::<usize>("jobs")
+        .copied()
+        .unwrap_or_else(|| thread::available_parallelism().map_or(4, usize::from))
+        .min(files.len());
+
+    let (next, failed) = (AtomicUsize::new(0), AtomicUsize::new(0));
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while let Some(input) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    match job.run(input) {
+                        Ok(path) => println!("{} -> {}", input.display(), path.display()),
+                        Err(err) => {
+                            eprintln!("{}: {err}", input.display());
+                            failed.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    if failed.into_inner() > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
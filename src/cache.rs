@@ -0,0 +1,366 @@
+//! module containing the content addressed [`Cache`] of processed images,
+//! keyed on a hash of the upload, the effect, every option that affects the output
+//! and the effects config the options were resolved with
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+use axum::{
+    body::Bytes,
+    http::{header, HeaderMap},
+};
+use lru::LruCache;
+use rand::{thread_rng, Rng};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use image_web::{
+    config::EffectsConfig,
+    models::{FormatOption, FrameOption},
+    output::OutputFormat,
+};
+
+use crate::settings::{self, CacheConfig};
+
+lazy_static::lazy_static! {
+    /// the cache shared by every effect route, sized by the config
+    pub static ref CACHE: Cache = Cache::new(&settings::get().cache);
+}
+
+/// the in memory tier, with the total size of its entries
+struct Memory {
+    entries: LruCache<String, (OutputFormat, Bytes)>,
+    size: usize,
+}
+
+/// the files of the disk tier from the least to the most recently used, with their total size
+struct Files {
+    sizes: LruCache<String, u64>,
+    size: u64,
+}
+
+/// the disk tier, a directory holding one `<key>.<extension>` file per entry.
+///
+/// only the files named that way are ever read, counted or evicted,
+/// so it is safe to point it at a directory holding other files
+struct Disk {
+    dir: PathBuf,
+    /// maximum total size of the files, in bytes
+    budget: u64,
+    /// scanned from the directory the first time the tier is used
+    files: Mutex<Option<Files>>,
+}
+
+/// an in memory LRU of encoded outputs with a size budget,
+/// backed by an optional directory on disk with its own budget
+pub struct Cache {
+    enabled: bool,
+    memory: Mutex<Memory>,
+    /// maximum total size of the in memory entries, in bytes
+    memory_budget: usize,
+    /// the disk tier, if enabled
+    disk: Option<Disk>,
+}
+
+/// hashes everything that determines the output of an effect route into a cache key.
+///
+/// the sizes of `config` are part of it, since they decide the defaults and maximums the options resolve to,
+/// so that outputs cached on disk under another config are never served
+pub fn key<O: Serialize>(
+    effect: &str,
+    buffer: &[u8],
+    options: &O,
+    config: &EffectsConfig,
+    format: &FormatOption,
+    frames: &FrameOption,
+    accept: Option<&str>,
+) -> String {
+    let mut hasher = Sha256::new();
+
+    // every part is length prefixed so that no two requests can hash the same
+    for part in [
+        effect.as_bytes(),
+        buffer,
+        serde_json::to_string(options).unwrap_or_default().as_bytes(),
+        serde_json::to_string(config).unwrap_or_default().as_bytes(),
+        serde_json::to_string(format).unwrap_or_default().as_bytes(),
+        serde_json::to_string(frames).unwrap_or_default().as_bytes(),
+        accept.unwrap_or_default().as_bytes(),
+    ] {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+/// the strong `ETag` of a cache key
+pub fn etag(key: &str) -> String {
+    format!("\"{key}\"")
+}
+
+/// returns `true` if the `If-None-Match` header of the request matches `etag`
+pub fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers.get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+impl Cache {
+    pub fn new(config: &CacheConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            memory: Mutex::new(Memory {
+                entries: LruCache::unbounded(),
+                size: 0,
+            }),
+            memory_budget: config.memory_budget,
+            disk: config.disk.clone().map(|dir| Disk {
+                dir,
+                budget: config.disk_budget,
+                files: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// whether results are cached at all
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// locks the memory tier, recovering it if a previous holder panicked
+    fn memory(&self) -> MutexGuard<'_, Memory> {
+        self.memory.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// looks up `key` in memory, then on disk, promoting disk hits into memory.
+    ///
+    /// reads from the disk, so it should not be called on the async runtime
+    pub fn get(&self, key: &str) -> Option<(OutputFormat, Bytes)> {
+        if !self.enabled {
+            return None;
+        }
+
+        if let Some(entry) = self.memory().entries.get(key) {
+            return Some(entry.clone());
+        }
+
+        let (format, bytes) = self.disk.as_ref()?.read(key)?;
+
+        self.insert_memory(key.to_string(), format, bytes.clone());
+        Some((format, bytes))
+    }
+
+    /// stores an encoded output under `key`, in memory and on disk,
+    /// evicting the least recently used entries of each tier that go over its budget.
+    ///
+    /// writes to the disk, so it should not be called on the async runtime
+    pub fn insert(&self, key: String, format: OutputFormat, bytes: Bytes) {
+        if !self.enabled {
+            return;
+        }
+
+        if let Some(disk) = &self.disk {
+            disk.write(&key, format, &bytes);
+        }
+
+        self.insert_memory(key, format, bytes);
+    }
+
+    fn insert_memory(&self, key: String, format: OutputFormat, bytes: Bytes) {
+        // entries larger than the whole budget would only evict everything else
+        if bytes.len() > self.memory_budget {
+            return;
+        }
+
+        let mut memory = self.memory();
+        memory.size += bytes.len();
+
+        if let Some((_, old)) = memory.entries.put(key, (format, bytes)) {
+            memory.size -= old.len();
+        }
+
+        while memory.size > self.memory_budget {
+            match memory.entries.pop_lru() {
+                Some((_, (_, evicted))) => memory.size -= evicted.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+/// name of the file holding `key` in the disk tier
+fn file_name(key: &str, format: OutputFormat) -> String {
+    format!("{key}.{}", format.extension())
+}
+
+/// whether `name` is a file of the disk tier, named by [`file_name`] after a sha256 key
+fn is_cache_file(name: &str) -> bool {
+    name.split_once('.').map_or(false, |(key, extension)| {
+        key.len() == 64
+            && key.bytes().all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+            && OutputFormat::ALL.into_iter().any(|format| format.extension() == extension)
+    })
+}
+
+/// whether `name` is a temporary file of the disk tier, left behind by an interrupted [`Disk::write`]
+fn is_temporary_file(name: &str) -> bool {
+    name.strip_suffix(".tmp")
+        .and_then(|name| name.rsplit_once('.'))
+        .map_or(false, |(name, _)| is_cache_file(name))
+}
+
+impl Disk {
+    /// locks the files of the tier, scanning the directory the first time.
+    ///
+    /// the files found are ordered by their modification time,
+    /// and the temporary files of interrupted writes are removed
+    fn files(&self) -> MutexGuard<'_, Option<Files>> {
+        let mut files = self.files.lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        if files.is_none() {
+            let mut found = fs::read_dir(&self.dir)
+                .into_iter()
+                .flatten()
+                .filter_map(|entry| {
+                    let entry = entry.ok()?;
+                    let name = entry.file_name().into_string().ok()?;
+
+                    if is_temporary_file(&name) {
+                        let _ = fs::remove_file(entry.path());
+                        return None;
+                    }
+
+                    let metadata = entry.metadata().ok()?;
+                    if !is_cache_file(&name) || !metadata.is_file() {
+                        return None;
+                    }
+
+                    Some((name, metadata.len(), metadata.modified().ok()?))
+                })
+                .collect::<Vec<_>>();
+            found.sort_by_key(|(_, _, modified)| *modified);
+
+            let mut sizes = LruCache::unbounded();
+            let mut size = 0;
+            for (name, len, _) in found {
+                size += len;
+                sizes.put(name, len);
+            }
+
+            *files = Some(Files { sizes, size });
+        }
+
+        files
+    }
+
+    /// reads the file holding `key`, if any, marking it as the most recently used
+    fn read(&self, key: &str) -> Option<(OutputFormat, Bytes)> {
+        let (format, name) = OutputFormat::ALL
+            .into_iter()
+            .map(|format| (format, file_name(key, format)))
+            .find(|(_, name)| self.files()
+                .as_ref()
+                .map_or(false, |files| files.sizes.contains(name))
+            )?;
+
+        match fs::read(self.dir.join(&name)) {
+            Ok(bytes) => {
+                if let Some(files) = self.files().as_mut() {
+                    files.sizes.get(&name);
+                }
+                Some((format, Bytes::from(bytes)))
+            }
+            // removed from outside the server, forget it
+            Err(_) => {
+                if let Some(files) = self.files().as_mut() {
+                    if let Some(len) = files.sizes.pop(&name) {
+                        files.size -= len;
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// writes the file holding `key`, then evicts the least recently used files until the tier fits its budget.
+    ///
+    /// the bytes are written to a temporary file renamed into place once complete,
+    /// so that a concurrent read or a crash never leaves a truncated file behind
+    fn write(&self, key: &str, format: OutputFormat, bytes: &[u8]) {
+        // scanned before writing, so that the scan never removes the temporary file of this write
+        drop(self.files());
+
+        let name = file_name(key, format);
+        let temporary = self.dir.join(format!("{name}.{:016x}.tmp", thread_rng().gen::<u64>()));
+
+        let written = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&temporary, bytes))
+            .and_then(|_| fs::rename(&temporary, self.dir.join(&name)));
+        if written.is_err() {
+            let _ = fs::remove_file(&temporary);
+            return;
+        }
+
+        let mut files = self.files();
+        let files = match files.as_mut() {
+            Some(files) => files,
+            None => return,
+        };
+
+        files.size += bytes.len() as u64;
+        if let Some(old) = files.sizes.put(name, bytes.len() as u64) {
+            files.size -= old;
+        }
+
+        while files.size > self.budget {
+            match files.sizes.pop_lru() {
+                Some((name, len)) => {
+                    evict(&self.dir.join(name));
+                    files.size -= len;
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+/// removes an evicted file of the disk tier, it may already be gone
+fn evict(path: &Path) {
+    if let Err(err) = fs::remove_file(path) {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            tracing::warn!(path = %path.display(), %err, "failed to evict a cached image");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn only_hashed_names_are_cache_files() {
+        assert!(is_cache_file(&file_name(KEY, OutputFormat::Png)));
+        assert!(is_cache_file(&format!("{KEY}.gif")));
+        assert!(!is_cache_file(&format!("{KEY}.txt")));
+        assert!(!is_cache_file(&format!("{}.png", KEY.to_uppercase())));
+        assert!(!is_cache_file("config.png"));
+        assert!(!is_cache_file(KEY));
+    }
+
+    #[test]
+    fn temporary_files_are_named_after_a_cache_file() {
+        assert!(is_temporary_file(&format!("{KEY}.png.00000000000000ff.tmp")));
+        assert!(!is_temporary_file(&format!("{KEY}.png")));
+        assert!(!is_temporary_file("notes.png.00000000000000ff.tmp"));
+    }
+}
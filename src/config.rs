@@ -0,0 +1,113 @@
+//! module containing the [`EffectsConfig`] of the effects,
+//! the sizes they work at and the defaults and maximums of their size options.
+//!
+//! it is read from the `[effects]` table of the config file found by [`path`],
+//! by the server and the command line tool alike
+
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+
+/// path of the config file used when `CONFIG_PATH` is not set,
+/// it is fine for this one to be missing
+const DEFAULT_CONFIG_PATH: &str = "./config.toml";
+
+/// the config file to read, from `CONFIG_PATH` or `./config.toml` if it exists
+pub fn path() -> Option<PathBuf> {
+    std::env::var("CONFIG_PATH")
+        .ok()
+        .map(PathBuf::from)
+        .or_else(|| {
+            let path = PathBuf::from(DEFAULT_CONFIG_PATH);
+            path.exists().then_some(path)
+        })
+}
+
+/// the default and maximum value of a size option
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SizeConfig {
+    /// used when the option is not provided
+    pub default: u32,
+    /// largest value accepted for the option
+    pub max: u32,
+}
+
+impl SizeConfig {
+    /// resolves the value of a size option, applying the default and the maximum
+    pub fn resolve(self, size: Option<u32>) -> u32 {
+        size.unwrap_or(self.default)
+            .min(self.max)
+    }
+}
+
+/// sizes used by the effects
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EffectsConfig {
+    /// size of the longest side images are resized to before most effects
+    pub working_size: u32,
+    /// pixel size of each lego brick
+    pub lego_brick_size: u32,
+    /// pixel size of each minecraft block
+    pub minecraft_block_size: u32,
+    /// amount of bricks on the longest side for `lego`
+    pub lego: SizeConfig,
+    /// amount of blocks on the longest side for `minecraft`
+    pub minecraft: SizeConfig,
+    /// size of the longest side for `braille`, each character covering 2 by 4 pixels
+    pub braille: SizeConfig,
+    /// amount of characters per row for `ascii`
+    pub ascii: SizeConfig,
+    /// amount of characters on the longest side for `matrix`
+    pub matrix: SizeConfig,
+}
+
+impl Default for EffectsConfig {
+    fn default() -> Self {
+        Self {
+            working_size: 360,
+            lego_brick_size: 30,
+            minecraft_block_size: 20,
+            lego: SizeConfig { default: 40, max: 200 },
+            minecraft: SizeConfig { default: 70, max: 200 },
+            braille: SizeConfig { default: 130, max: 200 },
+            ascii: SizeConfig { default: 130, max: 200 },
+            matrix: SizeConfig { default: 80, max: 200 },
+        }
+    }
+}
+
+impl EffectsConfig {
+    /// every value out of its valid range, as messages naming the key under `effects.`
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        let positive = [
+            ("effects.working_size", self.working_size),
+            ("effects.lego_brick_size", self.lego_brick_size),
+            ("effects.minecraft_block_size", self.minecraft_block_size),
+        ];
+
+        for (key, value) in positive {
+            if value == 0 {
+                errors.push(format!("{key} must be greater than 0"));
+            }
+        }
+
+        let sizes = [
+            ("effects.lego", self.lego),
+            ("effects.minecraft", self.minecraft),
+            ("effects.braille", self.braille),
+            ("effects.ascii", self.ascii),
+            ("effects.matrix", self.matrix),
+        ];
+
+        for (key, size) in sizes {
+            if size.default == 0 || size.default > size.max {
+                errors.push(format!("{key}.default must be between 1 and {key}.max ({})", size.max));
+            }
+        }
+
+        errors
+    }
+}
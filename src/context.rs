@@ -0,0 +1,163 @@
+//! module containing the [`Context`] handed to every effect while it runs,
+//! letting long loops report their progress and stop early once it is cancelled
+
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc,
+};
+use serde::Serialize;
+use tokio::sync::watch;
+
+use crate::error::{Error, Result};
+
+/// the step a job is currently at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    /// waiting for a worker
+    Queued,
+    /// decoding the upload
+    Decoding,
+    /// applying the effect
+    Processing,
+    /// encoding the output
+    Encoding,
+}
+
+/// how far along a job is, sent to the subscribers of its [`Context`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Progress {
+    /// the step the job is at
+    pub stage: Stage,
+    /// percentage of the processing done, over every frame
+    pub percent: u8,
+    /// set once the job has finished, successfully or not
+    #[serde(skip)]
+    pub finished: bool,
+}
+
+/// shared between a running job and the request waiting on it
+#[derive(Debug, Clone)]
+pub struct Context {
+    /// set once nobody is waiting on the job anymore
+    cancelled: Arc<AtomicBool>,
+    /// the latest progress, watched by the subscribers
+    progress: Arc<watch::Sender<Progress>>,
+    /// index of the frame being processed and the amount of frames
+    frame: Arc<(AtomicU32, AtomicU32)>,
+    /// largest amount of pixels the output may add up to over every frame
+    max_pixels: u64,
+}
+
+/// cancels a [`Context`] when dropped,
+/// which happens for example when the client of a request disconnects
+pub struct CancelOnDrop(Context);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Self {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            progress: Arc::new(watch::channel(Progress {
+                stage: Stage::Queued,
+                percent: 0,
+                finished: false,
+            }).0),
+            frame: Arc::new((AtomicU32::new(0), AtomicU32::new(1))),
+            max_pixels: u64::MAX,
+        }
+    }
+
+    /// limits the output of the job to `max_pixels` over every frame,
+    /// unlimited by default, see [`Output::map_frames`](crate::output::Output::map_frames)
+    #[must_use]
+    pub fn with_max_pixels(mut self, max_pixels: u64) -> Self {
+        self.max_pixels = max_pixels;
+        self
+    }
+
+    /// the largest amount of pixels the output may add up to over every frame
+    pub fn max_pixels(&self) -> u64 {
+        self.max_pixels
+    }
+
+    /// stops the job at its next [`check`](Self::check)
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// moves the job on to `stage`
+    pub fn set_stage(&self, stage: Stage) {
+        self.progress.send_modify(|progress| progress.stage = stage);
+    }
+
+    /// how far along the job currently is
+    pub fn progress(&self) -> Progress {
+        *self.progress.borrow()
+    }
+
+    /// marks the job as finished, ending the streams of its subscribers
+    pub fn finish(&self) {
+        self.progress.send_modify(|progress| progress.finished = true);
+    }
+
+    /// subscribes to the progress of the job
+    pub fn subscribe(&self) -> watch::Receiver<Progress> {
+        self.progress.subscribe()
+    }
+
+    /// sets the frame of an animated input being processed,
+    /// so that [`advance`](Self::advance) reports the progress over every frame
+    pub fn set_frame(&self, index: u32, count: u32) {
+        self.frame.0.store(index, Ordering::Relaxed);
+        self.frame.1.store(count.max(1), Ordering::Relaxed);
+    }
+
+    /// reports that `done` out of `total` iterations of the current frame are done
+    /// and [`check`](Self::check)s whether to go on,
+    /// meant to be called at the start of every iteration of long loops
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn advance(&self, done: u32, total: u32) -> Result<()> {
+        let (index, count) = (
+            u64::from(self.frame.0.load(Ordering::Relaxed)),
+            u64::from(self.frame.1.load(Ordering::Relaxed)),
+        );
+        let total = u64::from(total.max(1));
+        let percent = ((index * total + u64::from(done)) * 100 / (count * total))
+            .min(100) as u8;
+
+        self.progress.send_if_modified(|progress| {
+            let changed = progress.percent != percent;
+            progress.percent = percent;
+            changed
+        });
+
+        self.check()
+    }
+
+    /// returns a guard cancelling the context once it is dropped
+    pub fn cancel_on_drop(&self) -> CancelOnDrop {
+        CancelOnDrop(self.clone())
+    }
+
+    /// fails with [`Error::Cancelled`] once the context has been cancelled,
+    /// meant to be called between the iterations of long loops
+    pub fn check(&self) -> Result<()> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(Error::Cancelled);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,419 @@
+//! module containing the [`Effect`] trait implemented by every processing function
+//! and the [`Registry`] used to look them up by name
+
+use std::{collections::HashMap, ops::RangeInclusive, sync::Arc};
+use ril::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    assets::Assets,
+    config::EffectsConfig,
+    context::Context,
+    error::{Error, FieldError, Result},
+    output::Output,
+};
+
+/// the kind of output an effect produces (for a still input image)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputKind {
+    /// a single still image
+    Static,
+    /// an animated sequence of frames
+    Animated,
+}
+
+/// the type of an effect option
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParameterType {
+    /// a whole number
+    Integer,
+    /// a decimal number
+    Number,
+    /// `true` or `false`
+    Boolean,
+}
+
+/// the schema of a single option of an effect, as listed by `/effects`
+#[derive(Debug, Clone, Serialize)]
+pub struct Parameter {
+    /// name of the option, as used in the query string
+    pub name: &'static str,
+    /// type of the option
+    #[serde(rename = "type")]
+    pub kind: ParameterType,
+    /// short description of the option
+    pub description: &'static str,
+    /// value used when the option is not provided, `null` if it has none
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub default: Value,
+    /// smallest valid value, for numeric options
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minimum: Option<Value>,
+    /// largest valid value, for numeric options
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maximum: Option<Value>,
+}
+
+impl Parameter {
+    /// an [`Integer`](ParameterType::Integer) option valid within `range`
+    pub fn integer(name: &'static str, description: &'static str, default: i64, range: RangeInclusive<i64>) -> Self {
+        Self {
+            name,
+            kind: ParameterType::Integer,
+            description,
+            default: json!(default),
+            minimum: Some(json!(range.start())),
+            maximum: Some(json!(range.end())),
+        }
+    }
+
+    /// a [`Number`](ParameterType::Number) option valid within `range`
+    pub fn number(name: &'static str, description: &'static str, default: f64, range: RangeInclusive<f64>) -> Self {
+        Self {
+            name,
+            kind: ParameterType::Number,
+            description,
+            default: json!(default),
+            minimum: Some(json!(range.start())),
+            maximum: Some(json!(range.end())),
+        }
+    }
+
+    /// an optional [`Integer`](ParameterType::Integer) option valid within `range`, without a default
+    pub fn optional_integer(name: &'static str, description: &'static str, range: RangeInclusive<i64>) -> Self {
+        Self {
+            name,
+            kind: ParameterType::Integer,
+            description,
+            default: Value::Null,
+            minimum: Some(json!(range.start())),
+            maximum: Some(json!(range.end())),
+        }
+    }
+
+    /// a [`Boolean`](ParameterType::Boolean) option
+    pub fn boolean(name: &'static str, description: &'static str, default: bool) -> Self {
+        Self {
+            name,
+            kind: ParameterType::Boolean,
+            description,
+            default: json!(default),
+            minimum: None,
+            maximum: None,
+        }
+    }
+
+    /// the [`FieldError`] of this option, with its range
+    fn error(&self, message: impl Into<String>) -> FieldError {
+        FieldError {
+            field: self.name.to_string(),
+            message: message.into(),
+            minimum: self.minimum.clone(),
+            maximum: self.maximum.clone(),
+        }
+    }
+
+    /// converts `raw`, as given in a query string or form field, into the JSON value of the option
+    pub fn parse(&self, raw: &str) -> std::result::Result<Value, FieldError> {
+        let value = match self.kind {
+            ParameterType::Integer => raw.parse::<i64>()
+                .map(|value| json!(value))
+                .map_err(|_| self.error("must be an integer"))?,
+            ParameterType::Number => raw.parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite())
+                .map(|value| json!(value))
+                .ok_or_else(|| self.error("must be a number"))?,
+            ParameterType::Boolean => raw.parse::<bool>()
+                .map(|value| json!(value))
+                .map_err(|_| self.error("must be true or false"))?,
+        };
+
+        self.check(&value)?;
+        Ok(value)
+    }
+
+    /// checks that `value` is of the type of the option and within its range,
+    /// `null` standing for the option not being provided
+    pub fn check(&self, value: &Value) -> std::result::Result<(), FieldError> {
+        if value.is_null() {
+            return Ok(());
+        }
+
+        let (valid, message) = match self.kind {
+            ParameterType::Integer => (value.is_i64() || value.is_u64(), "must be an integer"),
+            ParameterType::Number => (value.is_number(), "must be a number"),
+            ParameterType::Boolean => (value.is_boolean(), "must be true or false"),
+        };
+        if !valid {
+            return Err(self.error(message));
+        }
+
+        if let (Some(number), Some(minimum), Some(maximum)) = (value.as_f64(), &self.minimum, &self.maximum) {
+            if minimum.as_f64().map_or(false, |minimum| number < minimum)
+                || maximum.as_f64().map_or(false, |maximum| number > maximum)
+            {
+                return Err(self.error(format!("must be between {minimum} and {maximum}")));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// converts the string `fields` of a query string or form into the JSON options described by `parameters`,
+/// failing with [`Error::InvalidFields`] listing every field that is invalid.
+/// fields that are not options are ignored
+pub fn parse_fields(parameters: &[Parameter], fields: &HashMap<String, String>) -> Result<Map<String, Value>> {
+    let mut options = Map::new();
+    let mut errors = Vec::new();
+
+    for parameter in parameters {
+        if let Some(raw) = fields.get(parameter.name) {
+            match parameter.parse(raw) {
+                Ok(value) => {
+                    options.insert(parameter.name.to_string(), value);
+                }
+                Err(err) => errors.push(err),
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(options)
+    } else {
+        Err(Error::InvalidFields(errors))
+    }
+}
+
+/// checks the JSON `options` against `parameters`,
+/// failing with [`Error::InvalidFields`] listing every option that is invalid
+pub fn validate(parameters: &[Parameter], options: &Value) -> Result<()> {
+    let options = match options {
+        Value::Null => return Ok(()),
+        Value::Object(options) => options,
+        _ => return Err(Error::InvalidOption("the options must be an object".to_string())),
+    };
+
+    let errors = parameters.iter()
+        .filter_map(|parameter| options.get(parameter.name)
+            .and_then(|value| parameter.check(value).err())
+        )
+        .collect::<Vec<_>>();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::InvalidFields(errors))
+    }
+}
+
+/// the description of an effect, as listed by `/effects`
+#[derive(Debug, Clone, Serialize)]
+pub struct EffectInfo {
+    /// see [`Effect::NAME`]
+    pub name: &'static str,
+    /// the route the effect is served on
+    pub route: String,
+    /// see [`Effect::DESCRIPTION`]
+    pub description: &'static str,
+    /// see [`Effect::OUTPUT`]
+    pub output: OutputKind,
+    /// see [`Effect::parameters`]
+    pub options: Vec<Parameter>,
+    /// whether every asset of the effect loaded, see [`Assets::check`]
+    pub available: bool,
+}
+
+/// an image processing effect, exposed as `POST /{NAME}`
+/// and usable as a step of a pipeline
+pub trait Effect: Send + Sync + 'static {
+    /// struct to deserialize the optional query arguments into,
+    /// use [`crate::models::NoArgs`] to represent no arguments.
+    /// it should deny unknown fields, so that a misspelled option is rejected rather than ignored
+    type Options: DeserializeOwned + Serialize + Clone + Send + Sync + 'static;
+
+    /// name of the effect, also used as its route
+    const NAME: &'static str;
+
+    /// short description of what the effect does
+    const DESCRIPTION: &'static str;
+
+    /// the kind of output the effect produces with its default options
+    const OUTPUT: OutputKind = OutputKind::Static;
+
+    /// the assets the effect uses, see [`crate::assets::NAMES`].
+    /// the effect is unavailable if any of them failed to load
+    const ASSETS: &'static [&'static str] = &[];
+
+    /// the schema of every field of [`Effect::Options`],
+    /// with the defaults and ranges the effect uses with `config`
+    fn parameters(_config: &EffectsConfig) -> Vec<Parameter> {
+        Vec::new()
+    }
+
+    /// validates the JSON `options` against [`Effect::parameters`] and deserializes them,
+    /// failing with [`Error::InvalidFields`] listing every option of the wrong type or out of its range
+    fn parse_options(options: Value, config: &EffectsConfig) -> Result<Self::Options> {
+        validate(&Self::parameters(config), &options)?;
+
+        serde_json::from_value::<Self::Options>(
+            if options.is_null() { Value::Object(Map::new()) } else { options }
+        )
+            .map_err(|err| Error::InvalidOption(format!("{err} for {}", Self::NAME)))
+    }
+
+    /// whether the output for `options` may be cached,
+    /// which is only the case if it is the same every time
+    fn cacheable(_options: &Self::Options) -> bool {
+        true
+    }
+
+    /// applies the effect to a single image,
+    /// producing either a still image or an animated sequence.
+    ///
+    /// long running effects should [`check`](Context::check) the `context` inside their loops
+    fn apply(
+        &self,
+        image: Image<Rgba>,
+        options: Self::Options,
+        assets: &Assets,
+        config: &EffectsConfig,
+        context: &Context,
+    ) -> Result<Output>;
+}
+
+/// object safe counterpart of [`Effect`], implemented for every effect
+/// so that effects with different option types can be stored in one [`Registry`]
+pub trait DynEffect: Send + Sync {
+    /// see [`Effect::NAME`]
+    fn name(&self) -> &'static str;
+
+    /// describes the effect and its options with `config`
+    fn info(&self, config: &EffectsConfig) -> EffectInfo;
+
+    /// see [`Effect::ASSETS`]
+    fn assets(&self) -> &'static [&'static str];
+
+    /// checks the JSON `options` without applying the effect, see [`Effect::parse_options`]
+    fn validate(&self, options: &Value, config: &EffectsConfig) -> Result<()>;
+
+    /// deserializes `options` from JSON and applies the effect to `output`,
+    /// to every frame of it if it is animated.
+    ///
+    /// fails with [`Error::TooLarge`] as soon as the output exceeds the [`max_pixels`](Context::max_pixels) of `context`
+    fn apply_json(
+        &self,
+        output: Output,
+        options: Value,
+        assets: &Assets,
+        config: &EffectsConfig,
+        context: &Context,
+    ) -> Result<Output>;
+}
+
+impl<E: Effect> DynEffect for E {
+    fn name(&self) -> &'static str {
+        E::NAME
+    }
+
+    fn info(&self, config: &EffectsConfig) -> EffectInfo {
+        EffectInfo {
+            name: E::NAME,
+            route: format!("/{}", E::NAME),
+            description: E::DESCRIPTION,
+            output: E::OUTPUT,
+            options: E::parameters(config),
+            available: true,
+        }
+    }
+
+    fn assets(&self) -> &'static [&'static str] {
+        E::ASSETS
+    }
+
+    fn validate(&self, options: &Value, config: &EffectsConfig) -> Result<()> {
+        E::parse_options(options.clone(), config)
+            .map(drop)
+    }
+
+    fn apply_json(
+        &self,
+        output: Output,
+        options: Value,
+        assets: &Assets,
+        config: &EffectsConfig,
+        context: &Context,
+    ) -> Result<Output> {
+        assets.check(E::ASSETS)?;
+        let options = E::parse_options(options, config)?;
+
+        match output {
+            Output::Static(image) => {
+                let output = self.apply(image, options, assets, config, context)?;
+                output.check_pixels(context.max_pixels())?;
+                Ok(output)
+            }
+            Output::Animated(sequence) => Output::map_frames(
+                sequence,
+                true,
+                context.max_pixels(),
+                |image| self.apply(image, options.clone(), assets, config, context),
+            ),
+        }
+    }
+}
+
+/// something effects can be registered into, keeping their type,
+/// see [`crate::effects::register`]
+pub trait Register: Sized {
+    /// adds an effect
+    #[must_use]
+    fn register<E: Effect>(self, effect: E) -> Self;
+}
+
+/// a collection of every available effect
+#[derive(Default)]
+pub struct Registry {
+    effects: Vec<Arc<dyn DynEffect>>,
+}
+
+impl Registry {
+    /// creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// looks up an effect by its name
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn DynEffect>> {
+        self.effects
+            .iter()
+            .find(|effect| effect.name() == name)
+    }
+
+    /// iterates over every effect, in the order they were registered
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<dyn DynEffect>> {
+        self.effects.iter()
+    }
+
+    /// describes every effect with `config`, in the order they were registered,
+    /// with whether their assets loaded
+    pub fn info(&self, assets: &Assets, config: &EffectsConfig) -> Vec<EffectInfo> {
+        self.iter()
+            .map(|effect| EffectInfo {
+                available: assets.check(effect.assets()).is_ok(),
+                ..effect.info(config)
+            })
+            .collect()
+    }
+}
+
+impl Register for Registry {
+    fn register<E: Effect>(mut self, effect: E) -> Self {
+        self.effects.push(Arc::new(effect));
+        self
+    }
+}
@@ -0,0 +1,427 @@
+//! module containing the [`Effect`] implementations for every processing function in `functions.rs`,
+//! and the [`REGISTRY`] holding all of them
+
+use ril::prelude::*;
+
+#[allow(clippy::wildcard_imports)]
+use crate::{
+    assets::{self, Assets},
+    config::{EffectsConfig, SizeConfig},
+    context::Context,
+    effect::{Effect, OutputKind, Parameter, Register, Registry},
+    functions::{
+        self,
+        DEFAULT_BRAILLE_THRESHOLD,
+        DEFAULT_PAINT_INTENSITY,
+        DEFAULT_PAINT_RADIUS,
+    },
+    helpers::{DEFAULT_SHAPE_BLOCK, DEFAULT_SHAPE_DENSITY},
+    error::Result,
+    models::*,
+    output::Output,
+};
+
+lazy_static::lazy_static! {
+    /// every effect, in the order they are listed
+    pub static ref REGISTRY: Registry = register(Registry::new());
+}
+
+/// registers every effect into `registry`, in the order they are listed
+pub fn register<R: Register>(registry: R) -> R {
+    registry
+        .register(Lego)
+        .register(Minecraft)
+        .register(Paint)
+        .register(Frost)
+        .register(Braille)
+        .register(Ascii)
+        .register(Matrix)
+        .register(Lines)
+        .register(Balls)
+        .register(Squares)
+        .register(BlackWhite)
+        .register(Edge)
+        .register(Emboss)
+        .register(HueRotate)
+}
+
+/// the `size` option of an effect, with its default and maximum taken from the config
+fn size_parameter(description: &'static str, size: SizeConfig) -> Parameter {
+    Parameter::integer("size", description, i64::from(size.default), 1..=i64::from(size.max))
+}
+
+/// the `seed` option of the randomized effects,
+/// which are only cached when it is provided
+fn seed_parameter() -> Parameter {
+    Parameter::optional_integer("seed", "Seed for the same output every time, random when left empty", 0..=i64::from(u32::MAX))
+}
+
+/// the options shared by the shape effects, see [`ShapesOption`]
+fn shape_parameters() -> Vec<Parameter> {
+    vec![
+        Parameter::integer("block", "Size of each shape", i64::from(DEFAULT_SHAPE_BLOCK), 1..=50),
+        Parameter::integer("density", "Amount of shapes drawn per frame", i64::from(DEFAULT_SHAPE_DENSITY), 1..=20000),
+        Parameter::boolean("gif", "Whether to animate the output", true),
+        seed_parameter(),
+    ]
+}
+
+/// see [`functions::lego`]
+pub struct Lego;
+
+impl Effect for Lego {
+    type Options = SizeOption;
+
+    const NAME: &'static str = "lego";
+    const DESCRIPTION: &'static str = "Builds the image out of lego bricks";
+    const ASSETS: &'static [&'static str] = &[assets::LEGO];
+
+    fn parameters(config: &EffectsConfig) -> Vec<Parameter> {
+        vec![
+            size_parameter("Amount of bricks on the longest side", config.lego),
+        ]
+    }
+
+    fn apply(&self, image: Image<Rgba>, options: SizeOption, assets: &Assets, config: &EffectsConfig, context: &Context) -> Result<Output> {
+        functions::lego(image, options, assets, config, context)
+            .map(Output::from)
+    }
+}
+
+/// see [`functions::minecraft`]
+pub struct Minecraft;
+
+impl Effect for Minecraft {
+    type Options = SizeOption;
+
+    const NAME: &'static str = "minecraft";
+    const DESCRIPTION: &'static str = "Builds the image out of minecraft blocks";
+    const ASSETS: &'static [&'static str] = &[assets::MINECRAFT];
+
+    fn parameters(config: &EffectsConfig) -> Vec<Parameter> {
+        vec![
+            size_parameter("Amount of blocks on the longest side", config.minecraft),
+        ]
+    }
+
+    fn apply(&self, image: Image<Rgba>, options: SizeOption, assets: &Assets, config: &EffectsConfig, context: &Context) -> Result<Output> {
+        functions::minecraft(image, options, assets, config, context)
+            .map(Output::from)
+    }
+}
+
+/// see [`functions::paint`]
+pub struct Paint;
+
+impl Effect for Paint {
+    type Options = PaintOption;
+
+    const NAME: &'static str = "paint";
+    const DESCRIPTION: &'static str = "Paints the image with oil paint strokes";
+
+    fn parameters(_config: &EffectsConfig) -> Vec<Parameter> {
+        vec![
+            Parameter::integer("radius", "Radius of the paint strokes", i64::from(DEFAULT_PAINT_RADIUS), 1..=20),
+            Parameter::number("intensity", "Intensity of the paint strokes", DEFAULT_PAINT_INTENSITY, 1.0..=100.0),
+        ]
+    }
+
+    fn apply(&self, image: Image<Rgba>, options: PaintOption, _assets: &Assets, config: &EffectsConfig, _context: &Context) -> Result<Output> {
+        functions::paint(image, options, config)
+            .map(Output::from)
+    }
+}
+
+/// see [`functions::frost`]
+pub struct Frost;
+
+impl Effect for Frost {
+    type Options = NoArgs;
+
+    const NAME: &'static str = "frost";
+    const DESCRIPTION: &'static str = "Puts the image behind frosted glass";
+
+    fn apply(&self, image: Image<Rgba>, options: NoArgs, _assets: &Assets, config: &EffectsConfig, _context: &Context) -> Result<Output> {
+        functions::frost(image, options, config)
+            .map(Output::from)
+    }
+}
+
+/// see [`functions::braille`]
+pub struct Braille;
+
+impl Effect for Braille {
+    type Options = BrailleOption;
+
+    const NAME: &'static str = "braille";
+    const DESCRIPTION: &'static str = "Draws the image with braille characters";
+    const ASSETS: &'static [&'static str] = &[assets::UNICODE_FONT];
+
+    fn parameters(config: &EffectsConfig) -> Vec<Parameter> {
+        vec![
+            Parameter::integer("threshold", "Grayscale value below which a dot is filled", i64::from(DEFAULT_BRAILLE_THRESHOLD), 0..=255),
+            Parameter::boolean("invert", "Whether to invert the dots", false),
+            size_parameter("Size of the longest side, each character covering 2 by 4 pixels", config.braille),
+        ]
+    }
+
+    fn apply(&self, image: Image<Rgba>, options: BrailleOption, assets: &Assets, config: &EffectsConfig, context: &Context) -> Result<Output> {
+        functions::braille(image, options, assets, config, context)
+            .map(Output::from)
+    }
+}
+
+/// see [`functions::ascii`]
+pub struct Ascii;
+
+impl Effect for Ascii {
+    type Options = AsciiOption;
+
+    const NAME: &'static str = "ascii";
+    const DESCRIPTION: &'static str = "Draws the image with ascii punctuation characters";
+    const ASSETS: &'static [&'static str] = &[assets::MONOSPACE_FONT];
+
+    fn parameters(config: &EffectsConfig) -> Vec<Parameter> {
+        vec![
+            Parameter::boolean("invert", "Whether to invert the characters", false),
+            size_parameter("Amount of characters per row", config.ascii),
+        ]
+    }
+
+    fn apply(&self, image: Image<Rgba>, options: AsciiOption, assets: &Assets, config: &EffectsConfig, context: &Context) -> Result<Output> {
+        functions::ascii(image, options, assets, config, context)
+            .map(Output::from)
+    }
+}
+
+/// see [`functions::matrix`]
+pub struct Matrix;
+
+impl Effect for Matrix {
+    type Options = MatrixOption;
+
+    const NAME: &'static str = "matrix";
+    const DESCRIPTION: &'static str = "Draws the image with flickering colored characters";
+    const OUTPUT: OutputKind = OutputKind::Animated;
+    const ASSETS: &'static [&'static str] = &[assets::CODE_FONT];
+
+    fn parameters(config: &EffectsConfig) -> Vec<Parameter> {
+        vec![
+            size_parameter("Amount of characters on the longest side", config.matrix),
+            Parameter::boolean("num_only", "Whether to only use digits", false),
+            seed_parameter(),
+        ]
+    }
+
+    fn cacheable(options: &MatrixOption) -> bool {
+        options.seed.is_some()
+    }
+
+    fn apply(&self, image: Image<Rgba>, options: MatrixOption, assets: &Assets, config: &EffectsConfig, context: &Context) -> Result<Output> {
+        functions::matrix(image, options, assets, config, context)
+            .map(Output::from)
+    }
+}
+
+/// see [`functions::lines`]
+pub struct Lines;
+
+impl Effect for Lines {
+    type Options = ShapesOption;
+
+    const NAME: &'static str = "lines";
+    const DESCRIPTION: &'static str = "Draws the image with diagonal lines";
+    const OUTPUT: OutputKind = OutputKind::Animated;
+
+    fn parameters(_config: &EffectsConfig) -> Vec<Parameter> {
+        shape_parameters()
+    }
+
+    fn cacheable(options: &ShapesOption) -> bool {
+        options.seed.is_some()
+    }
+
+    fn apply(&self, image: Image<Rgba>, options: ShapesOption, _assets: &Assets, config: &EffectsConfig, context: &Context) -> Result<Output> {
+        functions::lines(image, options, config, context)
+            .map(Output::from)
+    }
+}
+
+/// see [`functions::balls`]
+pub struct Balls;
+
+impl Effect for Balls {
+    type Options = ShapesOption;
+
+    const NAME: &'static str = "balls";
+    const DESCRIPTION: &'static str = "Draws the image with circles";
+    const OUTPUT: OutputKind = OutputKind::Animated;
+
+    fn parameters(_config: &EffectsConfig) -> Vec<Parameter> {
+        shape_parameters()
+    }
+
+    fn cacheable(options: &ShapesOption) -> bool {
+        options.seed.is_some()
+    }
+
+    fn apply(&self, image: Image<Rgba>, options: ShapesOption, _assets: &Assets, config: &EffectsConfig, context: &Context) -> Result<Output> {
+        functions::balls(image, options, config, context)
+            .map(Output::from)
+    }
+}
+
+/// see [`functions::squares`]
+pub struct Squares;
+
+impl Effect for Squares {
+    type Options = ShapesOption;
+
+    const NAME: &'static str = "squares";
+    const DESCRIPTION: &'static str = "Draws the image with squares";
+    const OUTPUT: OutputKind = OutputKind::Animated;
+
+    fn parameters(_config: &EffectsConfig) -> Vec<Parameter> {
+        shape_parameters()
+    }
+
+    fn cacheable(options: &ShapesOption) -> bool {
+        options.seed.is_some()
+    }
+
+    fn apply(&self, image: Image<Rgba>, options: ShapesOption, _assets: &Assets, config: &EffectsConfig, context: &Context) -> Result<Output> {
+        functions::squares(image, options, config, context)
+            .map(Output::from)
+    }
+}
+
+/// see [`functions::black_white`]
+pub struct BlackWhite;
+
+impl Effect for BlackWhite {
+    type Options = SmoothOption;
+
+    const NAME: &'static str = "black_white";
+    const DESCRIPTION: &'static str = "Turns the image into black and white pixels";
+
+    fn parameters(_config: &EffectsConfig) -> Vec<Parameter> {
+        vec![
+            Parameter::boolean("smooth", "Whether to smooth the pixels", false),
+        ]
+    }
+
+    fn apply(&self, image: Image<Rgba>, options: SmoothOption, _assets: &Assets, _config: &EffectsConfig, _context: &Context) -> Result<Output> {
+        functions::black_white(image, options)
+            .map(Output::from)
+    }
+}
+
+/// see [`functions::edge`]
+pub struct Edge;
+
+impl Effect for Edge {
+    type Options = NoArgs;
+
+    const NAME: &'static str = "edge";
+    const DESCRIPTION: &'static str = "Highlights the edges of the image";
+
+    fn apply(&self, image: Image<Rgba>, options: NoArgs, _assets: &Assets, config: &EffectsConfig, _context: &Context) -> Result<Output> {
+        functions::edge(image, options, config)
+            .map(Output::from)
+    }
+}
+
+/// see [`functions::emboss`]
+pub struct Emboss;
+
+impl Effect for Emboss {
+    type Options = NoArgs;
+
+    const NAME: &'static str = "emboss";
+    const DESCRIPTION: &'static str = "Embosses the image";
+
+    fn apply(&self, image: Image<Rgba>, options: NoArgs, _assets: &Assets, config: &EffectsConfig, _context: &Context) -> Result<Output> {
+        functions::emboss(image, options, config)
+            .map(Output::from)
+    }
+}
+
+/// see [`functions::hue_rotate`]
+pub struct HueRotate;
+
+impl Effect for HueRotate {
+    type Options = NoArgs;
+
+    const NAME: &'static str = "hue_rotate";
+    const DESCRIPTION: &'static str = "Rotates the hue of the image a full 360 degrees";
+    const OUTPUT: OutputKind = OutputKind::Animated;
+
+    fn apply(&self, image: Image<Rgba>, options: NoArgs, _assets: &Assets, config: &EffectsConfig, context: &Context) -> Result<Output> {
+        functions::hue_rotate(image, options, config, context)
+            .map(Output::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Map, Value};
+
+    use super::*;
+
+    /// checks that the parameters of every effect registered into it match the fields of its options
+    struct RoundTrip(EffectsConfig);
+
+    impl Register for RoundTrip {
+        fn register<E: Effect>(self, _effect: E) -> Self {
+            let parameters = E::parameters(&self.0);
+            let options = parameters.iter()
+                .map(|parameter| (
+                    parameter.name.to_string(),
+                    if parameter.default.is_null() { parameter.minimum.clone().unwrap_or_default() } else { parameter.default.clone() },
+                ))
+                .collect::<Map<String, Value>>();
+
+            let parsed = E::parse_options(Value::Object(options.clone()), &self.0)
+                .unwrap_or_else(|err| panic!("the parameters of {} are not all options: {err}", E::NAME));
+            let fields = serde_json::to_value(parsed)
+                .expect("options serialize");
+
+            let mut names = options.keys().collect::<Vec<_>>();
+            let mut fields = fields.as_object()
+                .expect("options are a struct")
+                .keys()
+                .collect::<Vec<_>>();
+            names.sort();
+            fields.sort();
+            assert_eq!(names, fields, "the parameters of {} do not match its options", E::NAME);
+
+            self
+        }
+    }
+
+    #[test]
+    fn every_parameter_is_an_option() {
+        register(RoundTrip(EffectsConfig::default()));
+    }
+
+    /// rejects an unknown option on every effect registered into it
+    struct UnknownOption(EffectsConfig);
+
+    impl Register for UnknownOption {
+        fn register<E: Effect>(self, _effect: E) -> Self {
+            let options = json!({ "not_an_option": true });
+
+            assert!(
+                E::parse_options(options, &self.0).is_err(),
+                "{} accepts an unknown option", E::NAME,
+            );
+
+            self
+        }
+    }
+
+    #[test]
+    fn unknown_options_are_rejected() {
+        register(UnknownOption(EffectsConfig::default()));
+    }
+}
@@ -0,0 +1,123 @@
+//! module containing the [`Error`] type returned by the effects,
+//! with a stable error code that clients can branch on and its structured details
+
+use std::fmt;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// why a single option is invalid, listed by [`Error::InvalidFields`]
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    /// name of the option
+    pub field: String,
+    /// what is wrong with its value
+    pub message: String,
+    /// smallest valid value, for numeric options
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minimum: Option<Value>,
+    /// largest valid value, for numeric options
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maximum: Option<Value>,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.field, self.message)
+    }
+}
+
+/// every error that can occur while processing an image
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// the upload is not in an image format that can be decoded
+    UnsupportedFormat,
+    /// the upload is in a known image format but could not be decoded
+    CorruptImage(String),
+    /// the image exceeds one of the limits, measured in `unit`
+    TooLarge {
+        unit: &'static str,
+        actual: u64,
+        limit: u64,
+    },
+    /// one of the options is invalid
+    InvalidOption(String),
+    /// some options are of the wrong type or out of their range
+    InvalidFields(Vec<FieldError>),
+    /// processing was stopped because its [`Context`](crate::context::Context) was cancelled
+    Cancelled,
+    /// the effect is disabled because an asset it uses failed to load
+    Unavailable {
+        asset: &'static str,
+        reason: String,
+    },
+    /// an unexpected error while processing the image
+    Internal(String),
+}
+
+/// shortcut typealias for results of the effects
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// the stable, machine readable code of the error
+    pub const fn code(&self) -> &'static str {
+        match self {
+            Self::UnsupportedFormat => "unsupported_format",
+            Self::CorruptImage(_) => "corrupt_image",
+            Self::TooLarge { .. } => "too_large",
+            Self::InvalidOption(_) => "invalid_option",
+            Self::InvalidFields(_) => "invalid_fields",
+            Self::Cancelled => "cancelled",
+            Self::Unavailable { .. } => "unavailable",
+            Self::Internal(_) => "internal",
+        }
+    }
+
+    /// extra structured information about the error, if any
+    pub fn details(&self) -> Option<Value> {
+        match self {
+            Self::InvalidFields(fields) => Some(json!({ "fields": fields })),
+            Self::TooLarge { unit, actual, limit } => Some(json!({
+                "unit": unit,
+                "actual": actual,
+                "limit": limit,
+            })),
+            Self::Unavailable { asset, reason } => Some(json!({
+                "asset": asset,
+                "reason": reason,
+            })),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedFormat => write!(f, "The image provided is not in a supported format"),
+            Self::CorruptImage(err) => write!(f, "The image provided could not be decoded: {err}"),
+            Self::TooLarge { unit, actual, limit } =>
+                write!(f, "The image provided has {actual} {unit} which exceeds the limit of {limit} {unit}"),
+            Self::InvalidOption(err) => write!(f, "Invalid options: {err}"),
+            Self::InvalidFields(fields) => write!(
+                f,
+                "Invalid options: {}",
+                fields.iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            Self::Cancelled => write!(f, "Processing the image was cancelled"),
+            Self::Unavailable { asset, .. } =>
+                write!(f, "This effect is unavailable because its asset {asset} failed to load"),
+            Self::Internal(err) => write!(f, "Something went wrong: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ril::Error> for Error {
+    fn from(err: ril::Error) -> Self {
+        Self::Internal(err.to_string())
+    }
+}
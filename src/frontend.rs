@@ -0,0 +1,76 @@
+//! module serving the static frontend files, for the requests matching no route.
+//!
+//! they are served from `server.frontend` if set, otherwise from the copy of `./frontend`
+//! built into the executable with the `embed` feature, otherwise from `./frontend`
+
+use std::{io, path::Path};
+use axum::{
+    body::Body,
+    handler::Handler,
+    routing::{get_service, MethodRouter},
+};
+use tower::util::ServiceExt;
+use tower_http::services::ServeDir;
+
+use image_web::error::Error as ImageError;
+
+use crate::{not_found, response::Error, settings};
+
+#[cfg(feature = "embed")]
+mod embedded {
+    use axum::{
+        http::{header, Uri},
+        response::{IntoResponse, Response},
+    };
+    use include_dir::{include_dir, Dir};
+
+    use crate::not_found;
+
+    /// the copy of `./frontend` built into the executable
+    static FRONTEND: Dir<'static> = include_dir!("$CARGO_MANIFEST_DIR/frontend");
+
+    /// serves the embedded file at the path of `uri`,
+    /// the `index.html` of directories like [`ServeDir`](tower_http::services::ServeDir)
+    pub async fn serve(uri: Uri) -> Response {
+        let path = uri.path().trim_start_matches('/');
+        let path = if path.is_empty() || path.ends_with('/') {
+            format!("{path}index.html")
+        } else {
+            path.to_string()
+        };
+
+        match FRONTEND.get_file(&path) {
+            Some(file) => (
+                [(header::CONTENT_TYPE, mime_guess::from_path(&path).first_or_octet_stream().to_string())],
+                file.contents(),
+            ).into_response(),
+            None => not_found().await,
+        }
+    }
+}
+
+/// serves the files of `dir`, answering [`not_found`] for the missing ones
+fn serve_dir(dir: &Path) -> MethodRouter<Body> {
+    get_service(
+        ServeDir::new(dir)
+            .not_found_service(
+                not_found
+                    .into_service()
+                    .map_err(|_| io::Error::from(io::ErrorKind::Other))
+            )
+    )
+        .handle_error(|err: io::Error| async move {
+            Error::from(ImageError::Internal(err.to_string()))
+        })
+}
+
+/// the fallback of the router, serving the frontend files
+pub fn fallback() -> MethodRouter<Body> {
+    match &settings::get().server.frontend {
+        Some(dir) => serve_dir(dir),
+        #[cfg(feature = "embed")]
+        None => axum::routing::get(embedded::serve),
+        #[cfg(not(feature = "embed"))]
+        None => serve_dir(Path::new("./frontend")),
+    }
+}
@@ -1,42 +1,45 @@
 //! File containing all processing functions for indivdual endpoints
 
-use std::{path::Path, collections::HashMap};
-use rand::{thread_rng, Rng};
-use std::fs::read_dir;
+use std::time::Duration;
+use rand::Rng;
 use photon_rs::effects;
 use ril::prelude::*;
 
 #[allow(clippy::wildcard_imports)]
 use crate::{
+    assets::Assets,
+    config::EffectsConfig,
+    context::Context,
+    error::Result,
     helpers::*,
     models::*,
 };
 
 
-/// constant representing the pixel size of each lego brick
-const LEGO_SIZE: u32 = 30;
+/// default radius of the paint strokes for `paint`
+pub const DEFAULT_PAINT_RADIUS: i32 = 5;
 
-/// constant representing the pixel size of each minecraft block
-const MCSIZE: u32 = 20;
+/// default intensity of the paint strokes for `paint`
+pub const DEFAULT_PAINT_INTENSITY: f64 = 60.0;
+
+/// default threshold (grayscale) below which a `braille` dot is filled
+pub const DEFAULT_BRAILLE_THRESHOLD: u8 = 90;
+
+/// delay between each frame of the `hue_rotate` animation
+const HUE_ROTATE_DELAY: Duration = Duration::from_millis(50);
+
+/// delay between each frame of the `matrix` animation
+const MATRIX_DELAY: Duration = Duration::from_millis(200);
+
+/// amount of frames of the `matrix` animation
+const MATRIX_FRAMES: u32 = 5;
 
 /// shortcut typealias for return type of all functions
-type R = ril::Result<Image<Rgba>>;
+type R = Result<Image<Rgba>>;
 /// shortcut typealias but for for animated results
-type RGif = ril::Result<ImageSequence<Rgba>>;
+type RGif = Result<ImageSequence<Rgba>>;
 
 lazy_static::lazy_static! {
-    /// gray lego brick asset
-    static ref LEGO: Image<Rgb> = Image::open("./assets/lego.png")
-        .unwrap();
-    /// unicode font used for `braille` (supports braille glyphs)
-    static ref UNICODE_FONT: Font = Font::open("./assets/unicode.ttf", 30.0)
-        .unwrap();
-    /// monospace font (consolas) used for `ascii` (equal in spacing)
-    static ref MONOSPACE_FONT: Font = Font::open("./assets/monospace.ttf", 30.0)
-        .unwrap();
-    /// "programming / code" font used for `matrix`
-    static ref CODE_FONT: Font = Font::open("./assets/monaco-linux.ttf", 30.0)
-        .unwrap();
     /// constant storing all the characters used in the `ascii` function
     static ref ASCII_CHARS: Vec<&'static str> = vec![
         "@", "#", "S", "%", "?", "*", "+", ";", ":", ",", ".", " ",
@@ -49,111 +52,71 @@ lazy_static::lazy_static! {
         "'", "(", ")", "*", "+", ",", "-", ".", "/", ":", ";", "<", "=", ">", "?", "@", "[", r"\",
         "]", "^", "_", "`", "{", "|", "}", "~", " ", "\t", "\n", "\r", "\x0b", "\x0c",
     ];
-
-    /// mapping containing all minecraft assets stored as (color: image) pairs
-    static ref MC_IMAGES: HashMap<(u8, u8, u8, u8), Image<Rgba>> = {
-        let mut failed = 0;
-        let mut map = HashMap::new();
-
-        for file in read_dir("./assets/minecraft").unwrap() {
-            let file = file.unwrap();
-
-            if file.file_name()
-                .into_string()
-                .map_or(false, |s| !Path::new(&s)
-                    .extension()
-                    .map_or(false, |ext| ext.eq_ignore_ascii_case("png"))
-                )
-            {
-                continue;
-            }
-
-            #[allow(clippy::option_if_let_else)]
-            if let Ok(block) =
-                Image::<Rgba>::open(file.path())
-            {
-                let single = block.clone()
-                    .resized(1, 1, ResizeAlgorithm::Bilinear);
-                map.insert(
-                    single.pixel(0, 0).as_rgba_tuple(),
-                    block.resized(MCSIZE, MCSIZE, ResizeAlgorithm::Bilinear),
-                );
-            } else {
-                failed += 1;
-            }
-        }
-
-        println!("Loaded {} minecraft blocks", map.len());
-        println!("Failed to load {failed} images");
-        map
-    };
-
-    /// a collection of all colors (palette) of the minecraft assets
-    static ref MC_SAMPLE: Vec<(u8, u8, u8, u8)> = MC_IMAGES
-        .keys()
-        .copied()
-        .collect();
-
 }
 
 
 /// builds an image out of lego blocks
-/// of provided `size`, defaulting to 40 blocks
-#[allow(clippy::unnecessary_wraps, clippy::many_single_char_names)]
-pub fn lego(image: Image<Rgba>, SizeOption { size }: SizeOption) -> R {
+/// of provided `size`, defaulting to `effects.lego.default` blocks
+#[allow(clippy::many_single_char_names)]
+pub fn lego(image: Image<Rgba>, SizeOption { size }: SizeOption, assets: &Assets, config: &EffectsConfig, context: &Context) -> R {
+    let lego = assets.lego()?;
+    let brick = config.lego_brick_size;
     let (mut x, mut y) = (0u32, 0u32);
     let image = resize_to(
         image,
-        u32::from(size.unwrap_or(40))
+        config.lego.resolve(size)
     );
     let mut base = Image::<Rgba>::new(
-        image.width() * LEGO_SIZE,
-        image.height() * LEGO_SIZE,
+        image.width() * brick,
+        image.height() * brick,
         Rgba::transparent(),
     );
 
-    for row in image.pixels() {
+    for (row, done) in image.pixels().into_iter().zip(0..) {
+        context.advance(done, image.height())?;
         for pixel in row {
             if pixel.a > 0 {
                 base.paste(x, y, {
-                    let (r, g, b) = LEGO.bands();
+                    let (r, g, b) = lego.bands();
                     &Image::from_bands((
                         colorize_lego_band(r, i32::from(pixel.r)),
                         colorize_lego_band(g, i32::from(pixel.g)),
                         colorize_lego_band(b, i32::from(pixel.b)),
-                        Image::new(LEGO_SIZE, LEGO_SIZE, L::new(pixel.a))
+                        Image::new(brick, brick, L::new(pixel.a))
                     ))
                 });
             }
-            x += LEGO_SIZE;
+            x += brick;
         }
         x = 0;
-        y += LEGO_SIZE;
+        y += brick;
     }
 
     Ok(base)
 }
 
 /// builds an image out of minecraft blocks
-/// of provided `size`, defaulting to 70 blocks
-#[allow(clippy::unnecessary_wraps)]
-pub fn minecraft(image: Image<Rgba>, SizeOption { size }: SizeOption) -> R {
+/// of provided `size`, defaulting to `effects.minecraft.default` blocks
+pub fn minecraft(image: Image<Rgba>, SizeOption { size }: SizeOption, assets: &Assets, config: &EffectsConfig, context: &Context) -> R {
+    let minecraft = assets.minecraft()?;
+    let block = config.minecraft_block_size;
     let (mut x, mut y) = (0u32, 0u32);
     let image = resize_to(
         image,
-        u32::from(size.unwrap_or(70))
+        config.minecraft.resolve(size)
     );
     let mut base = Image::<Rgba>::new(
-        image.width() * MCSIZE,
-        image.height() * MCSIZE,
+        image.width() * block,
+        image.height() * block,
         Rgba::transparent(),
     );
 
-    for row in image.pixels() {
+    for (row, done) in image.pixels().into_iter().zip(0..) {
+        context.advance(done, image.height())?;
         for pixel in row {
             if pixel.a > 0 {
                 base.paste(x, y, {
-                    let color = MC_SAMPLE.iter()
+                    let color = minecraft.palette.iter()
                         .min_by_key(|color|
                             u32::from(color.0.abs_diff(pixel.r)) +
                             u32::from(color.1.abs_diff(pixel.g)) +
@@ -163,30 +126,30 @@ pub fn minecraft(image: Image<Rgba>, SizeOption { size }: SizeOption) -> R {
                         .copied()
                         .unwrap();
 
-                    &MC_IMAGES.get(&color)
+                    &minecraft.blocks.get(&color)
                         .unwrap()
                         .clone()
                         .convert()
                 });
             }
-            x += MCSIZE;
+            x += block;
         }
         x = 0;
-        y += MCSIZE;
+        y += block;
     }
 
     Ok(base)
 }
 
 /// paints out an image
-pub fn paint(image: Image<Rgba>, PaintOption { radius, intensity }: PaintOption) -> R {
+pub fn paint(image: Image<Rgba>, PaintOption { radius, intensity }: PaintOption, config: &EffectsConfig) -> R {
     let image = resize_to(
-        image, 360,
+        image, config.working_size,
     );
     let mut img = to_photon(&image)?;
 
-    let radius = radius.unwrap_or(5);
-    let intensity = intensity.unwrap_or(60.0);
+    let radius = radius.unwrap_or(DEFAULT_PAINT_RADIUS);
+    let intensity = intensity.unwrap_or(DEFAULT_PAINT_INTENSITY);
     effects::oil(&mut img, radius, intensity);
 
     let image = to_ril(&img);
@@ -194,9 +157,9 @@ pub fn paint(image: Image<Rgba>, PaintOption { radius, intensity }: PaintOption)
 }
 
 /// frosted glass effect?
-pub fn frost(image: Image<Rgba>, _: NoArgs) -> R {
+pub fn frost(image: Image<Rgba>, _: NoArgs, config: &EffectsConfig) -> R {
     let image = resize_to(
-        image, 360,
+        image, config.working_size,
     );
     let mut img = to_photon(&image)?;
     effects::frosted_glass(&mut img);
@@ -206,9 +169,9 @@ pub fn frost(image: Image<Rgba>, _: NoArgs) -> R {
 }
 
 /// emboss effect
-pub fn emboss(image: Image<Rgba>, _: NoArgs) -> R {
+pub fn emboss(image: Image<Rgba>, _: NoArgs, config: &EffectsConfig) -> R {
     let image = resize_to(
-        image, 360,
+        image, config.working_size,
     );
     let mut img = to_photon(&image)?;
     photon_rs::conv::emboss(&mut img);
@@ -218,9 +181,9 @@ pub fn emboss(image: Image<Rgba>, _: NoArgs) -> R {
 }
 
 /// "edge" effect
-pub fn edge(image: Image<Rgba>, _: NoArgs) -> R {
+pub fn edge(image: Image<Rgba>, _: NoArgs, config: &EffectsConfig) -> R {
     let image = resize_to(
-        image, 360,
+        image, config.working_size,
     );
     let mut img = to_photon(&image)?;
     photon_rs::conv::edge_one(&mut img);
@@ -248,18 +211,22 @@ pub fn black_white(image: Image<Rgba>, SmoothOption { smooth }: SmoothOption) ->
 }
 
 /// rotates the hue (hsv) value of the image 360deg
-#[allow(clippy::unnecessary_wraps)]
-pub fn hue_rotate(image: Image<Rgba>, _: NoArgs) -> RGif {
+pub fn hue_rotate(image: Image<Rgba>, _: NoArgs, config: &EffectsConfig, context: &Context) -> RGif {
     let image = resize_to(
-        image, 360,
+        image, config.working_size,
     );
     let mut sequence =
-        ImageSequence::<Rgba>::new();
+        ImageSequence::<Rgba>::new()
+            .with_loop_count(LoopCount::Infinite);
 
-    for deg in (0..360).step_by(10) {
+    for (deg, done) in (0..360).step_by(10).zip(0..) {
+        context.advance(done, 36)?;
         let clone = image.clone()
             .hue_rotated(deg);
-        sequence.push_frame(Frame::from_image(clone));
+        sequence.push_frame(
+            Frame::from_image(clone)
+                .with_delay(HUE_ROTATE_DELAY)
+        );
     }
 
     Ok(sequence)
@@ -268,19 +235,19 @@ pub fn hue_rotate(image: Image<Rgba>, _: NoArgs) -> RGif {
 /// builds an image out of braille characters
 #[allow(
     clippy::cast_possible_truncation,
-    clippy::unnecessary_wraps,
     clippy::cast_sign_loss,
 )]
-pub fn braille(image: Image<Rgba>, BrailleOption { size, threshold, invert }: BrailleOption) -> R {
+pub fn braille(image: Image<Rgba>, BrailleOption { size, threshold, invert }: BrailleOption, assets: &Assets, config: &EffectsConfig, context: &Context) -> R {
     let image = resize_to(
         image,
-        u32::from(size.unwrap_or(130))
+        config.braille.resolve(size)
     );
     let w = (f64::from(image.width()) / 2.0).ceil() as usize;
     let h = (f64::from(image.height()) / 4.0).ceil() as usize;
     let mut mat = vec![vec![" ".to_string(); w]; h];
 
-    for x in 0..w {
+    for (x, done) in (0..w).zip(0..) {
+        context.advance(done, (image.width() + 1) / 2)?;
         for (y, row) in mat
             .iter_mut()
             .enumerate()
@@ -291,7 +258,7 @@ pub fn braille(image: Image<Rgba>, BrailleOption { size, threshold, invert }: Br
                 y * 4,
                 &image,
                 invert.unwrap_or(false),
-                u32::from(threshold.unwrap_or(90)),
+                u32::from(threshold.unwrap_or(DEFAULT_BRAILLE_THRESHOLD)),
             ).unwrap_or_else(|| ".".to_string());
         }
     }
@@ -302,63 +269,68 @@ pub fn braille(image: Image<Rgba>, BrailleOption { size, threshold, invert }: Br
         .collect::<Vec<String>>()
         .join("\n");
 
-    let canvas = draw_text(&UNICODE_FONT, text);
+    let canvas = draw_text(assets.unicode_font()?, text);
     Ok(canvas)
 }
 
 /// builds an image out of ascii punctuation characters
-#[allow(clippy::unnecessary_wraps)]
-pub fn ascii(image: Image<Rgba>, AsciiOption { size, invert }: AsciiOption) -> R {
+pub fn ascii(image: Image<Rgba>, AsciiOption { size, invert }: AsciiOption, assets: &Assets, config: &EffectsConfig, context: &Context) -> R {
     let mut image = ascii_resize(
         image,
-        u32::from(size.unwrap_or(130))
+        config.ascii.resolve(size)
     );
     if invert.unwrap_or(false) {
         image.invert();
     }
     let image = image.convert::<L>();
     let mut text = String::new();
-    for row in image.pixels() {
+    for (row, done) in image.pixels().into_iter().zip(0..) {
+        context.advance(done, image.height())?;
         for pixel in row {
             text.push_str(ASCII_CHARS[pixel.value() as usize / 25]);
         }
         text.push('\n');
     }
-    let canvas = draw_text(&MONOSPACE_FONT, text);
+    let canvas = draw_text(assets.monospace_font()?, text);
     Ok(canvas)
 }
 
 /// builds an image out of ascii punctuation characters
-#[allow(clippy::unnecessary_wraps)]
-pub fn matrix(image: Image<Rgba>, MatrixOption { size, num_only }: MatrixOption) -> ril::Result<ImageSequence<Rgb>> {
+pub fn matrix(image: Image<Rgba>, MatrixOption { size, num_only, seed }: MatrixOption, assets: &Assets, config: &EffectsConfig, context: &Context) -> RGif {
     let image = resize_to(
         image,
-        u32::from(size.unwrap_or(80))
+        config.matrix.resolve(size)
     );
-    let mut sequence = ImageSequence::<Rgb>::new();
-
-    for _ in 0..5 {
+    let mut sequence = ImageSequence::<Rgba>::new()
+        .with_loop_count(LoopCount::Infinite);
+    let font = assets.code_font()?;
+    let mut rng = seeded_rng(seed);
+    // sampled as `u32` rather than `usize` for the same output on every platform
+    let chars = u32::try_from(CHAR_SAMPLE.len())
+        .unwrap_or(u32::MAX);
+
+    for frame in 0..MATRIX_FRAMES {
         let (mut x, mut y) = (0u32, 0u32);
         let mut canvas = Image::<Rgb>::new(
             image.width() * 30,
             image.height() * 30,
             Rgb::black(),
         );
-        let mut rng = thread_rng();
-        for row in image.pixels() {
+        for (row, done) in image.pixels().into_iter().zip(0..) {
+            context.advance(frame * image.height() + done, MATRIX_FRAMES * image.height())?;
             for px in row {
                 if px.a > 0 {
                     let chr = if num_only.unwrap_or(false) {
-                        rng.gen_range(0..=9)
+                        rng.gen_range(0..=9u32)
                             .to_string()
                     } else {
-                        CHAR_SAMPLE[rng.gen_range(0..CHAR_SAMPLE.len())]
+                        CHAR_SAMPLE[rng.gen_range(0..chars) as usize]
                             .to_string()
                     };
                     let layout = TextLayout::new()
                         .with_wrap(WrapStyle::None)
                         .with_position(x, y)
-                        .with_basic_text(&CODE_FONT, chr, px.into_rgb());
+                        .with_basic_text(font, chr, px.into_rgb());
                     canvas.draw(&layout);
                 }
                 x += 30;
@@ -366,60 +338,69 @@ pub fn matrix(image: Image<Rgba>, MatrixOption { size, num_only }: MatrixOption)
             x = 0;
             y += 30;
         }
-        sequence.push_frame(Frame::from_image(canvas));
+        sequence.push_frame(
+            Frame::from_image(canvas.convert::<Rgba>())
+                .with_delay(MATRIX_DELAY)
+        );
     }
     Ok(sequence)
 }
 
 /// builds a shape out of diagonal lines
-#[allow(clippy::unnecessary_wraps)]
-pub fn lines(image: Image<Rgba>, ShapesOption { block, density, gif }: ShapesOption) -> RGif {
+pub fn lines(image: Image<Rgba>, ShapesOption { block, density, gif, seed }: ShapesOption, config: &EffectsConfig, context: &Context) -> RGif {
     let image = resize_to(
-        image, 360,
+        image, config.working_size,
     );
-    let mut sequence = ImageSequence::<Rgba>::new();
+    let mut sequence = ImageSequence::<Rgba>::new()
+        .with_loop_count(LoopCount::Infinite);
     let t = if gif.unwrap_or(true)
         { 3 } else { 1 };
+    let mut rng = seeded_rng(seed);
 
-    for _ in 0..t {
+    for done in 0..t {
+        context.advance(done, t)?;
         sequence.push_frame(
-            gen_shape_frame(&image, ShapeMethod::Line, block, density)
+            gen_shape_frame(&image, ShapeMethod::Line, block, density, &mut rng)
         );
     }
     Ok(sequence)
 }
 
 /// builds a shape out of circles
-#[allow(clippy::unnecessary_wraps)]
-pub fn balls(image: Image<Rgba>, ShapesOption { block, density, gif }: ShapesOption) -> RGif {
+pub fn balls(image: Image<Rgba>, ShapesOption { block, density, gif, seed }: ShapesOption, config: &EffectsConfig, context: &Context) -> RGif {
     let image = resize_to(
-        image, 360,
+        image, config.working_size,
     );
-    let mut sequence = ImageSequence::<Rgba>::new();
+    let mut sequence = ImageSequence::<Rgba>::new()
+        .with_loop_count(LoopCount::Infinite);
     let t = if gif.unwrap_or(true)
         { 3 } else { 1 };
+    let mut rng = seeded_rng(seed);
 
-    for _ in 0..t {
+    for done in 0..t {
+        context.advance(done, t)?;
         sequence.push_frame(
-            gen_shape_frame(&image, ShapeMethod::Ball, block, density)
+            gen_shape_frame(&image, ShapeMethod::Ball, block, density, &mut rng)
         );
     }
     Ok(sequence)
 }
 
 /// builds a shape out of squares
-#[allow(clippy::unnecessary_wraps)]
-pub fn squares(image: Image<Rgba>, ShapesOption { block, density, gif }: ShapesOption) -> RGif {
+pub fn squares(image: Image<Rgba>, ShapesOption { block, density, gif, seed }: ShapesOption, config: &EffectsConfig, context: &Context) -> RGif {
     let image = resize_to(
-        image, 360,
+        image, config.working_size,
     );
-    let mut sequence = ImageSequence::<Rgba>::new();
+    let mut sequence = ImageSequence::<Rgba>::new()
+        .with_loop_count(LoopCount::Infinite);
     let t = if gif.unwrap_or(true)
         { 3 } else { 1 };
+    let mut rng = seeded_rng(seed);
 
-    for _ in 0..t {
+    for done in 0..t {
+        context.advance(done, t)?;
         sequence.push_frame(
-            gen_shape_frame(&image, ShapeMethod::Square, block, density)
+            gen_shape_frame(&image, ShapeMethod::Square, block, density, &mut rng)
         );
     }
     Ok(sequence)
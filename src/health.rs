@@ -0,0 +1,123 @@
+//! module holding the [`Assets`] of the server,
+//! which are warmed up at startup so that the server is only ready once every one of them has loaded.
+//! until every one was tried, the processing routes answer `503` rather than load them on the async runtime.
+//! an effect whose asset failed to load is disabled and answers `503`,
+//! unless `server.require_assets` is set, in which case the server refuses to start
+//!
+//! - `GET /healthz` succeeds as long as the server is up
+//! - `GET /readyz` succeeds once every asset has been tried and loaded, listing those that failed otherwise
+//! - `GET /assets` reports which assets loaded and which failed
+
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use axum::{http::StatusCode, Json};
+use image_web::assets::{self, AssetStatus, Assets, Source};
+use serde_json::{json, Value};
+
+use crate::{
+    metrics,
+    response::{Error, Result},
+    settings,
+};
+
+lazy_static::lazy_static! {
+    /// the assets used by the effects, loaded from `server.assets` or the embedded copy.
+    ///
+    /// loading is blocking, so async handlers should go through [`loaded`] instead
+    pub static ref ASSETS: Assets = Assets::load(
+        &Source::new(settings::get().server.assets.as_deref()),
+        settings::get().effects.minecraft_block_size,
+    );
+}
+
+/// set once [`warm_up`] has finished
+static WARMED_UP: AtomicBool = AtomicBool::new(false);
+
+/// loads every asset, so that the first request using one does not pay for it.
+///
+/// loading is blocking, so it should not be called on the async runtime
+pub fn warm_up() {
+    for (name, status) in ASSETS.status() {
+        metrics::asset_loaded(name, status == AssetStatus::Loaded, 1);
+    }
+    if let Ok(minecraft) = &ASSETS.minecraft {
+        metrics::asset_loaded("minecraft block", true, minecraft.blocks.len() as u64);
+        metrics::asset_loaded("minecraft block", false, minecraft.failed as u64);
+    }
+
+    WARMED_UP.store(true, Ordering::SeqCst);
+}
+
+/// every asset that failed to load, with the reason
+pub fn failed() -> Vec<(&'static str, String)> {
+    ASSETS.status()
+        .into_iter()
+        .filter_map(|(name, status)| match status {
+            AssetStatus::Failed { error } => Some((name, error)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// whether every asset has been tried, the effects of those that failed are disabled
+pub fn ready() -> bool {
+    WARMED_UP.load(Ordering::SeqCst)
+}
+
+/// the [`ASSETS`] once [`warm_up`] has finished, `None` before
+pub fn loaded() -> Option<&'static Assets> {
+    ready().then(|| &*ASSETS)
+}
+
+/// checks that the assets `names` loaded,
+/// failing with [`Error::WarmingUp`] until [`warm_up`] has finished
+pub fn check(names: &[&'static str]) -> Result<()> {
+    loaded()
+        .ok_or(Error::WarmingUp)?
+        .check(names)
+        .map_err(Error::from)
+}
+
+/// handler for "/healthz", succeeding as long as the server is up
+#[allow(clippy::unused_async)]
+pub async fn healthz() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+/// handler for "/readyz",
+/// answering `503 Service Unavailable` until every asset has been tried,
+/// and for as long as any of them failed to load, listing those in `failed`
+#[allow(clippy::unused_async)]
+pub async fn readyz() -> (StatusCode, Json<Value>) {
+    if !ready() {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({ "status": "not_ready" })));
+    }
+
+    let failed = failed();
+    if failed.is_empty() {
+        (StatusCode::OK, Json(json!({ "status": "ready" })))
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, Json(json!({
+            "status": "not_ready",
+            "failed": failed
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>(),
+        })))
+    }
+}
+
+/// handler for "/assets", reporting the status of every asset
+#[allow(clippy::unused_async)]
+pub async fn assets() -> Json<BTreeMap<&'static str, AssetStatus>> {
+    if let Some(assets) = loaded() {
+        Json(assets.status())
+    } else {
+        Json(assets::NAMES
+            .into_iter()
+            .map(|name| (name, AssetStatus::Pending))
+            .collect())
+    }
+}
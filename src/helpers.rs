@@ -1,10 +1,21 @@
 //! File containing helper functions used by the
 //! individual processing functions for endpoints in `functions.rs`
 use photon_rs::PhotonImage;
+use std::time::Duration;
 use ril::prelude::*;
-use rand::{thread_rng, Rng};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use crate::braille_data::BRAILLE_DATA;
 
+/// delay between each frame of the animated shape effects
+const SHAPE_FRAME_DELAY: Duration = Duration::from_millis(150);
+
+/// default size of each individual shape for the shape effects
+pub const DEFAULT_SHAPE_BLOCK: u8 = 10;
+
+/// default amount of shapes drawn per frame for the shape effects
+pub const DEFAULT_SHAPE_DENSITY: u32 = 10000;
+
 /// enum for determining type of shape to draw for [`gen_shape_frame`]
 #[derive(Debug, Clone, Copy)]
 pub enum ShapeMethod {
@@ -176,25 +187,37 @@ pub fn ascii_resize(image: Image<Rgba>, cols: u32) -> Image<Rgba> {
     image.resized(cols, rows, ResizeAlgorithm::Bicubic)
 }
 
+/// the random number generator of the randomized effects,
+/// `ChaCha8` produces the same values on every platform for the same `seed`
+pub fn seeded_rng(seed: Option<u32>) -> ChaCha8Rng {
+    seed.map_or_else(
+        ChaCha8Rng::from_entropy,
+        |seed| ChaCha8Rng::seed_from_u64(u64::from(seed)),
+    )
+}
+
 /// generates a shape frame for balls / square / lines
 pub fn gen_shape_frame(
     image: &Image<Rgba>,
     method: ShapeMethod,
     size: Option<u8>,
     density: Option<u32>,
+    rng: &mut impl Rng,
 ) -> Frame<Rgba> {
-    let size = u32::from(size.unwrap_or(10));
-    let density = density.unwrap_or(10000);
+    let size = u32::from(size.unwrap_or(DEFAULT_SHAPE_BLOCK));
+    let density = density.unwrap_or(DEFAULT_SHAPE_DENSITY);
     let (width, height) = image.dimensions();
 
-    let mut rng = thread_rng();
     let mut canvas = Image::<Rgba>::new(
         width, height,
         Rgba::transparent(),
     );
+    // an empty image has no pixel to center a shape on
+    let density = if width == 0 || height == 0 { 0 } else { density };
     for _ in 0..density {
-        let x = rng.gen_range(1..width);
-        let y = rng.gen_range(1..height);
+        // shapes are centered away from the top and left edges, unless the image is a single pixel wide or tall
+        let x = rng.gen_range(1..width.max(2)).min(width - 1);
+        let y = rng.gen_range(1..height.max(2)).min(height - 1);
         let (x1, y1, x2, y2) =
             (
                 x.saturating_sub(size),
@@ -222,5 +245,27 @@ pub fn gen_shape_frame(
                     .draw(&mut canvas),
         }
     }
+    // each frame is rendered on its own transparent canvas,
+    // so it has to be cleared before the next one is drawn
     Frame::from_image(canvas)
-}
\ No newline at end of file
+        .with_delay(SHAPE_FRAME_DELAY)
+        .with_disposal(DisposalMethod::Background)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shapes_fit_single_pixel_images() {
+        for (width, height) in [(1, 1), (1, 8), (8, 1)] {
+            let image = Image::new(width, height, Rgba::white());
+
+            for method in [ShapeMethod::Line, ShapeMethod::Ball, ShapeMethod::Square] {
+                let frame = gen_shape_frame(&image, method, None, Some(16), &mut seeded_rng(Some(0)));
+
+                assert_eq!(frame.image().dimensions(), (width, height));
+            }
+        }
+    }
+}
@@ -0,0 +1,340 @@
+//! module containing the asynchronous job API,
+//! running an effect in the background and keeping its result around for a while
+//!
+//! - `POST /jobs/{effect}` takes the same upload and options as the effect route and returns a job id
+//! - `GET /jobs/{id}` reports the status of the job
+//! - `GET /jobs/{id}/events` streams the progress of the job as server sent events
+//! - `GET /jobs/{id}/result` serves the processed image once the job is done
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use axum::{
+    body::Bytes,
+    extract::{Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    Json,
+};
+use futures_util::{stream, Stream};
+use rand::{thread_rng, Rng};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::Instrument;
+
+use image_web::{
+    context::{Context, Progress, Stage},
+    effect::Effect,
+    output::OutputFormat,
+};
+
+use crate::{
+    cache::CACHE,
+    health,
+    pool::{self, POOL},
+    response::{self, Error, MultipartResult, QueryResult, Result},
+    settings::{self, JobsConfig},
+    wrapper,
+};
+
+lazy_static::lazy_static! {
+    /// every job that is running or whose result has not expired yet
+    pub static ref JOBS: JobStore = JobStore::new(&settings::get().jobs);
+}
+
+/// the status of a job, as reported by `GET /jobs/{id}`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    /// waiting for a worker
+    Queued,
+    /// decoding the upload
+    Decoding,
+    /// applying the effect
+    Processing,
+    /// encoding the output
+    Encoding,
+    /// finished, the result can be fetched
+    Done,
+    /// finished with an error
+    Failed,
+}
+
+impl From<Stage> for JobStatus {
+    fn from(stage: Stage) -> Self {
+        match stage {
+            Stage::Queued => Self::Queued,
+            Stage::Decoding => Self::Decoding,
+            Stage::Processing => Self::Processing,
+            Stage::Encoding => Self::Encoding,
+        }
+    }
+}
+
+/// a single job in the [`JobStore`]
+struct Job {
+    /// name of the effect the job runs
+    effect: &'static str,
+    /// shared with the running job, tracking its stage
+    context: Context,
+    /// the encoded output or the error, once finished
+    result: Option<Result<(OutputFormat, Vec<u8>)>>,
+    /// when the job finished, its result expires `ttl` after it
+    finished: Option<Instant>,
+}
+
+impl Job {
+    fn status(&self) -> JobStatus {
+        match &self.result {
+            Some(Ok(_)) => JobStatus::Done,
+            Some(Err(_)) => JobStatus::Failed,
+            None => self.context.progress().stage.into(),
+        }
+    }
+
+    /// the body of `GET /jobs/{id}`
+    fn describe(&self, id: &str) -> Value {
+        let status = self.status();
+
+        json!({
+            "id": id,
+            "effect": self.effect,
+            "status": status,
+            "percent": self.context.progress().percent,
+            "events": format!("/jobs/{id}/events"),
+            "result": (status == JobStatus::Done).then(|| format!("/jobs/{id}/result")),
+            "error": match &self.result {
+                Some(Err(err)) => json!({ "code": err.code(), "message": err.to_string() }),
+                _ => Value::Null,
+            },
+        })
+    }
+}
+
+/// in memory store of every job, evicting finished ones once their `ttl` passes
+pub struct JobStore {
+    jobs: Mutex<HashMap<String, Job>>,
+    /// how long results are kept once finished
+    ttl: Duration,
+    /// maximum amount of jobs stored at once
+    capacity: usize,
+}
+
+impl JobStore {
+    pub fn new(config: &JobsConfig) -> Self {
+        Self {
+            jobs: Mutex::new(HashMap::new()),
+            ttl: Duration::from_secs(config.ttl),
+            capacity: config.capacity,
+        }
+    }
+
+    /// locks the jobs, recovering them if a previous holder panicked
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Job>> {
+        self.jobs.lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// removes every finished job whose result has expired
+    pub fn evict(&self) {
+        let ttl = self.ttl;
+
+        self.lock()
+            .retain(|_, job| job.finished.map_or(true, |finished| finished.elapsed() < ttl));
+    }
+
+    /// starts `job` in the background, returning its id.
+    ///
+    /// fails with [`Error::Overloaded`] if the store or the queue of the [`POOL`] is full
+    pub fn submit<F>(&'static self, effect: &'static str, job: F) -> Result<String>
+    where
+        F: FnOnce(&Context) -> Result<(OutputFormat, Vec<u8>)> + Send + 'static,
+    {
+        self.evict();
+
+        let mut jobs = self.lock();
+        if jobs.len() >= self.capacity {
+            return Err(Error::Overloaded {
+                queued: jobs.len() as u64,
+                capacity: self.capacity as u64,
+                retry_after: self.ttl.as_secs(),
+            });
+        }
+
+        let ticket = POOL.reserve()?;
+        let context = Context::new()
+            .with_max_pixels(settings::get().limits.max_total_pixels);
+        let timeout = settings::get().limits.timeout_for(effect);
+        let id = format!("{:032x}", thread_rng().gen::<u128>());
+
+        jobs.insert(id.clone(), Job {
+            effect,
+            context: context.clone(),
+            result: None,
+            finished: None,
+        });
+        drop(jobs);
+
+        let key = id.clone();
+        let span = tracing::info_span!("job", id = %key, effect);
+        tokio::spawn(async move {
+            let running = context.clone();
+            let result = pool::with_timeout(&context, timeout, ticket.run(move || job(&running))).await;
+
+            match &result {
+                Ok(_) => tracing::info!("job done"),
+                Err(err) => err.log(),
+            }
+
+            if let Some(job) = self.lock().get_mut(&key) {
+                job.result = Some(result);
+                job.finished = Some(Instant::now());
+            }
+            context.finish();
+        }.instrument(span));
+
+        Ok(id)
+    }
+
+    /// evicts expired jobs every `ttl`, meant to be spawned once at startup
+    pub async fn evict_periodically(&'static self) {
+        let mut interval = tokio::time::interval(self.ttl);
+
+        loop {
+            interval.tick().await;
+            self.evict();
+        }
+    }
+}
+
+/// handler for `POST /jobs/{effect}`, mounted for every [`Effect`] next to its own route.
+///
+/// takes the same upload and options as the effect itself,
+/// answering `202 Accepted` with the id of the job.
+/// the job shares the [`CACHE`] of the effect route, serving a cached output without processing it again
+pub async fn submit<E: Effect>(
+    effect: Arc<E>,
+    query: QueryResult,
+    headers: HeaderMap,
+    multipart: MultipartResult,
+) -> Result<impl IntoResponse> {
+    let Query(query) = query.map_err(response::query)?;
+    let mut multipart = multipart.map_err(response::upload)?;
+    let config = &settings::get().effects;
+    health::check(E::ASSETS)?;
+    let accept = wrapper::accept_header(&headers);
+    let upload = wrapper::read_upload(&mut multipart, &E::parameters(config)).await?;
+    let (format, frames) = wrapper::common_options(&query, &upload.fields)?;
+    let options = wrapper::options::<E>(&query, &upload, config)?;
+    let buffer = upload.image;
+    let id = JOBS.submit(E::NAME, move |context| {
+        let key = wrapper::cache_key::<E>(&buffer, &options, &format, &frames, accept.as_deref());
+        if let Some((output_format, bytes)) = key.as_deref().and_then(|key| CACHE.get(key)) {
+            return Ok((output_format, bytes.to_vec()));
+        }
+
+        let (output_format, bytes) = wrapper::process(&*effect, &options, &buffer, &frames, &format, accept.as_deref(), context)?;
+        if let Some(key) = key {
+            CACHE.insert(key, output_format, Bytes::from(bytes.clone()));
+        }
+        Ok((output_format, bytes))
+    })?;
+
+    let location = format!("/jobs/{id}");
+    Ok((
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, location.clone())],
+        Json(json!({ "id": id, "location": location })),
+    ))
+}
+
+/// handler for `GET /jobs/{id}`
+#[allow(clippy::unused_async)]
+pub async fn status(Path(id): Path<String>) -> Result<Json<Value>> {
+    JOBS.lock()
+        .get(&id)
+        .map(|job| Json(job.describe(&id)))
+        .ok_or(Error::NotFound("job"))
+}
+
+/// handler for `GET /jobs/{id}/result`,
+/// failing with [`Error::NotReady`] while the job is still running
+/// and with the error of the job if it failed
+#[allow(clippy::unused_async)]
+pub async fn result(Path(id): Path<String>) -> Result<impl IntoResponse> {
+    let jobs = JOBS.lock();
+    let job = jobs.get(&id)
+        .ok_or(Error::NotFound("job"))?;
+
+    match &job.result {
+        Some(Ok((format, bytes))) => Ok((
+            [(header::CONTENT_TYPE, format.mime_type())],
+            bytes.clone(),
+        )),
+        Some(Err(err)) => Err(err.clone()),
+        None => Err(Error::NotReady(job.status())),
+    }
+}
+
+/// the final event of the stream of a job, carrying where to fetch its result
+fn finished_event(id: &str) -> Event {
+    let jobs = JOBS.lock();
+
+    match jobs.get(id).and_then(|job| job.result.as_ref()) {
+        Some(Ok(_)) => Event::default()
+            .event("done")
+            .data(json!({ "result": format!("/jobs/{id}/result") }).to_string()),
+        Some(Err(err)) => Event::default()
+            .event("failed")
+            .data(json!({ "code": err.code(), "message": err.to_string() }).to_string()),
+        None => Event::default()
+            .event("failed")
+            .data(json!({ "code": "not_found", "message": "The job has expired" }).to_string()),
+    }
+}
+
+/// an event reporting the progress of a job
+fn progress_event(progress: Progress) -> Event {
+    Event::default()
+        .event("progress")
+        .data(json!(progress).to_string())
+}
+
+/// handler for `GET /jobs/{id}/events`,
+/// streaming `progress` events while the job runs
+/// and ending with a `done` event carrying the location of the result, or a `failed` one
+#[allow(clippy::unused_async)]
+pub async fn events(Path(id): Path<String>) -> Result<Sse<impl Stream<Item = std::result::Result<Event, Infallible>>>> {
+    let receiver = JOBS.lock()
+        .get(&id)
+        .map(|job| job.context.subscribe())
+        .ok_or(Error::NotFound("job"))?;
+
+    // the current progress is sent first, then every change until the job finishes
+    let events = stream::unfold(Some((receiver, true)), move |state| {
+        let id = id.clone();
+
+        async move {
+            let (mut receiver, first) = state?;
+
+            if !first && receiver.changed().await.is_err() {
+                return Some((Ok(finished_event(&id)), None));
+            }
+
+            let progress = *receiver.borrow_and_update();
+            if progress.finished {
+                Some((Ok(finished_event(&id)), None))
+            } else {
+                Some((Ok(progress_event(progress)), Some((receiver, false))))
+            }
+        }
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
@@ -0,0 +1,21 @@
+//! the image processing behind the web app, usable on its own:
+//! every [`effects`] with its options, applied with an explicit [`assets::Assets`] handle
+//! and [`config::EffectsConfig`].
+//!
+//! load the assets once with [`assets::Assets::load`],
+//! then look effects up by name in the [`effects::REGISTRY`] or call the [`functions`] directly.
+//!
+//! the library only depends on the image processing crates,
+//! the `image-web` server around it is built with the `server` feature
+
+mod braille_data;
+pub mod helpers;
+pub mod functions;
+pub mod models;
+pub mod output;
+pub mod effect;
+pub mod effects;
+pub mod error;
+pub mod config;
+pub mod context;
+pub mod assets;
@@ -0,0 +1,52 @@
+//! module setting up the structured JSON logs of the server,
+//! with a span per request carrying its `X-Request-Id`
+
+use axum::http::{HeaderValue, Request};
+use rand::{thread_rng, Rng};
+use tower_http::request_id::{MakeRequestId, RequestId};
+use tracing::Span;
+use tracing_subscriber::EnvFilter;
+
+/// name of the header carrying the id of a request,
+/// taken from the request if the client sent one and echoed back on the response
+pub const REQUEST_ID: &str = "x-request-id";
+
+/// installs the JSON subscriber, filtered by `RUST_LOG` and defaulting to `info`
+pub fn init() {
+    tracing_subscriber::fmt()
+        .json()
+        .with_env_filter(
+            EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| EnvFilter::new("info"))
+        )
+        .with_current_span(true)
+        .with_span_list(true)
+        .init();
+}
+
+/// assigns a random id to requests that came without an `X-Request-Id`
+#[derive(Debug, Clone, Copy)]
+pub struct MakeRequestHex;
+
+impl MakeRequestId for MakeRequestHex {
+    fn make_request_id<B>(&mut self, _request: &Request<B>) -> Option<RequestId> {
+        HeaderValue::from_str(&format!("{:032x}", thread_rng().gen::<u128>()))
+            .ok()
+            .map(RequestId::new)
+    }
+}
+
+/// the span of a request, which every log emitted while handling it is nested in
+pub fn make_span<B>(request: &Request<B>) -> Span {
+    let id = request.headers()
+        .get(REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    tracing::info_span!(
+        "request",
+        id,
+        method = %request.method(),
+        path = request.uri().path(),
+    )
+}
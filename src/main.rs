@@ -1,31 +1,39 @@
 use axum::{
     body::Body,
-    handler::Handler,
     http::StatusCode,
-    routing::{get_service, get, post},
+    middleware,
     response::{Html, IntoResponse, Response},
     Router,
 };
-use ril::prelude::*;
-use std::{io, net::SocketAddr};
-use tower::util::ServiceExt;
-use tower_http::services::ServeDir;
-use crate::wrap_fn as wrap;
-
-mod braille_data;
-mod helpers;
-mod functions;
-mod wrapper;
-mod models;
+use std::net::SocketAddr;
+use tower::ServiceBuilder;
+use tower_http::{
+    request_id::{PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::{DefaultOnResponse, TraceLayer},
+    LatencyUnit,
+};
+use tracing::Level;
 
-const MAX_IMAGE_SIZE: usize = 15_000_000;
+use settings::Config;
+
+mod settings;
+mod response;
+mod wrapper;
+mod pipeline;
+mod probe;
+mod openapi;
+mod pool;
+mod jobs;
+mod cache;
+mod metrics;
+mod logging;
+mod health;
+mod routes;
+mod frontend;
 
 /// a simple function that creates a server,
 /// serving the router and then running the server.
-async fn run(app: Router<Body>, port: Option<u16>) {
-    let port = port.unwrap_or(8080);
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
-
+async fn run(app: Router<Body>, addr: SocketAddr) {
     let server = axum::Server::bind(&addr)
         .serve(app.into_make_service())
         .with_graceful_shutdown(async {
@@ -34,7 +42,7 @@ async fn run(app: Router<Body>, port: Option<u16>) {
                 .expect("Failed to await for SIGINT");
         });
 
-    println!("Server initialized");
+    tracing::info!(%addr, "server initialized");
     server.await.expect("Failed to start server");
 }
 
@@ -53,48 +61,79 @@ pub async fn root() -> Html<String> {
     Html(include_str!("../frontend/index.html").to_string())
 }
 
-#[tokio::main]
-async fn main() {
-    dotenv::dotenv()
-        .ok();
+/// builds the router and serves it on the configured address
+async fn serve() {
+    let config = settings::get();
 
-    let app: Router<Body> = Router::new()
-        .route("/", get(root))
-        .route("/lego", post(wrap!(functions::lego, models::SizeOption)))
-        .route("/minecraft", post(wrap!(functions::minecraft, models::SizeOption)))
-        .route("/paint", post(wrap!(functions::paint, models::PaintOption)))
-        .route("/frost", post(wrap!(functions::frost, models::NoArgs)))
-        .route("/braille", post(wrap!(functions::braille, models::BrailleOption)))
-        .route("/ascii", post(wrap!(functions::ascii, models::AsciiOption)))
-        .route("/matrix", post(wrap!(functions::matrix, models::MatrixOption)))
-        .route("/lines", post(wrap!(functions::lines, models::ShapesOption)))
-        .route("/balls", post(wrap!(functions::balls, models::ShapesOption)))
-        .route("/squares", post(wrap!(functions::squares, models::ShapesOption)))
-        .route("/black_white", post(wrap!(functions::black_white, models::SmoothOption)))
-        .route("/edge", post(wrap!(functions::edge, models::NoArgs)))
-        .route("/emboss", post(wrap!(functions::emboss, models::NoArgs)))
-        .route("/hue_rotate", post(wrap!(functions::hue_rotate, models::NoArgs)))
-        .fallback(
-            get_service(
-                ServeDir::new("./frontend/")
-                .not_found_service(
-                    not_found
-                        .into_service()
-                        .map_err(|_| io::Error::from(io::ErrorKind::Other))
+    let app: Router<Body> = routes::mount(Router::new())
+        .fallback(frontend::fallback())
+        .layer(middleware::from_fn(metrics::track))
+        .layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::new(
+                    logging::REQUEST_ID.parse().unwrap(),
+                    logging::MakeRequestHex,
+                ))
+                .layer(
+                    TraceLayer::new_for_http()
+                        .make_span_with(logging::make_span)
+                        .on_response(
+                            DefaultOnResponse::new()
+                                .level(Level::INFO)
+                                .latency_unit(LatencyUnit::Millis)
+                        )
                 )
-            )
-            .handle_error(|err| async move {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Something went wrong: {err}"),
-                )
-            }),
+                .layer(PropagateRequestIdLayer::new(logging::REQUEST_ID.parse().unwrap()))
         );
 
-    let port = std::env::var("PORT")
-        .unwrap()
-        .parse::<u16>()
+    tokio::spawn(jobs::JOBS.evict_periodically());
+
+    // without `require_assets`, the server starts right away and the effects of missing assets are disabled
+    if config.server.require_assets {
+        tokio::task::spawn_blocking(health::warm_up)
+            .await
+            .expect("Failed to load the assets");
+
+        let failed = health::failed();
+        if !failed.is_empty() {
+            for (asset, reason) in failed {
+                tracing::error!(asset, %reason, "required asset failed to load");
+            }
+            std::process::exit(1);
+        }
+    } else {
+        tokio::task::spawn_blocking(health::warm_up);
+    }
+
+    run(app, SocketAddr::new(config.server.host, config.server.port)).await;
+}
+
+fn main() {
+    dotenv::dotenv()
         .ok();
+    logging::init();
+
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            tracing::error!(%err, "invalid configuration");
+            std::process::exit(1);
+        }
+    };
+
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    runtime.enable_all();
+
+    if let Some(threads) = config.runtime.worker_threads {
+        runtime.worker_threads(threads);
+    }
+    if let Some(threads) = config.runtime.blocking_threads {
+        runtime.max_blocking_threads(threads);
+    }
+
+    settings::init(config);
 
-    run(app, port).await;
+    runtime.build()
+        .expect("Failed to build the async runtime")
+        .block_on(serve());
 }
@@ -0,0 +1,180 @@
+//! module containing the prometheus metrics of the server, exposed on `/metrics`
+//!
+//! - `http_requests_total` and `http_request_duration_seconds`, by route and status
+//! - `image_stage_duration_seconds`, the time spent decoding, applying the effect and encoding
+//! - `image_input_bytes` and `image_input_dimension_pixels`, the size of the uploads
+//! - `pool_*`, the occupancy of the [`POOL`]
+//! - `asset_loads_total`, the assets loaded by the effects
+
+use std::time::Instant;
+use axum::{
+    extract::MatchedPath,
+    http::{header, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use prometheus::{
+    exponential_buckets,
+    register_histogram,
+    register_histogram_vec,
+    register_int_counter_vec,
+    register_int_gauge,
+    Encoder,
+    Histogram,
+    HistogramTimer,
+    HistogramVec,
+    IntCounterVec,
+    IntGauge,
+    TextEncoder,
+};
+
+use image_web::error::Error as ImageError;
+
+use crate::{pool::POOL, response::Result};
+
+lazy_static::lazy_static! {
+    static ref REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "http_requests_total",
+        "Amount of requests handled, by route and status",
+        &["route", "status"]
+    ).unwrap();
+    static ref REQUEST_SECONDS: HistogramVec = register_histogram_vec!(
+        "http_request_duration_seconds",
+        "Time taken to answer requests, by route",
+        &["route"],
+        exponential_buckets(0.005, 2.0, 14).unwrap()
+    ).unwrap();
+    static ref STAGE_SECONDS: HistogramVec = register_histogram_vec!(
+        "image_stage_duration_seconds",
+        "Time spent in each stage of processing an image, by effect",
+        &["effect", "stage"],
+        exponential_buckets(0.001, 2.0, 17).unwrap()
+    ).unwrap();
+    static ref INPUT_BYTES: Histogram = register_histogram!(
+        "image_input_bytes",
+        "Size of the uploaded images in bytes",
+        exponential_buckets(1024.0, 4.0, 10).unwrap()
+    ).unwrap();
+    static ref INPUT_DIMENSIONS: HistogramVec = register_histogram_vec!(
+        "image_input_dimension_pixels",
+        "Width and height of the decoded uploads in pixels",
+        &["axis"],
+        exponential_buckets(16.0, 2.0, 10).unwrap()
+    ).unwrap();
+    static ref POOL_WORKERS: IntGauge = register_int_gauge!(
+        "pool_workers",
+        "Amount of workers processing images"
+    ).unwrap();
+    static ref POOL_RUNNING: IntGauge = register_int_gauge!(
+        "pool_running",
+        "Amount of jobs currently running on a worker"
+    ).unwrap();
+    static ref POOL_QUEUED: IntGauge = register_int_gauge!(
+        "pool_queued",
+        "Amount of jobs waiting for a worker"
+    ).unwrap();
+    static ref POOL_CAPACITY: IntGauge = register_int_gauge!(
+        "pool_capacity",
+        "Maximum amount of jobs waiting for a worker"
+    ).unwrap();
+    static ref ASSET_LOADS: IntCounterVec = register_int_counter_vec!(
+        "asset_loads_total",
+        "Amount of assets loaded, by asset and result",
+        &["asset", "result"]
+    ).unwrap();
+}
+
+/// a stage of processing an image, timed by [`time_stage`]
+#[derive(Debug, Clone, Copy)]
+pub enum Stage {
+    Decode,
+    Effect,
+    Encode,
+}
+
+impl Stage {
+    const fn label(self) -> &'static str {
+        match self {
+            Self::Decode => "decode",
+            Self::Effect => "effect",
+            Self::Encode => "encode",
+        }
+    }
+}
+
+/// times a stage of processing an image, see [`time_stage`]
+pub struct StageTimer {
+    stage: Stage,
+    timer: Option<HistogramTimer>,
+}
+
+impl Drop for StageTimer {
+    fn drop(&mut self) {
+        if let Some(timer) = self.timer.take() {
+            let seconds = timer.stop_and_record();
+            tracing::info!(stage = self.stage.label(), seconds, "stage finished");
+        }
+    }
+}
+
+/// starts timing `stage` of `effect`,
+/// observed and logged once the returned timer is dropped
+pub fn time_stage(effect: &str, stage: Stage) -> StageTimer {
+    StageTimer {
+        stage,
+        timer: Some(STAGE_SECONDS.with_label_values(&[effect, stage.label()]).start_timer()),
+    }
+}
+
+/// records the size of a decoded upload
+pub fn observe_input(bytes: usize, (width, height): (u32, u32)) {
+    INPUT_BYTES.observe(bytes as f64);
+    INPUT_DIMENSIONS.with_label_values(&["width"])
+        .observe(f64::from(width));
+    INPUT_DIMENSIONS.with_label_values(&["height"])
+        .observe(f64::from(height));
+}
+
+/// records `count` loads of `asset`, either succeeded or failed
+pub fn asset_loaded(asset: &str, ok: bool, count: u64) {
+    ASSET_LOADS.with_label_values(&[asset, if ok { "ok" } else { "error" }])
+        .inc_by(count);
+}
+
+/// middleware counting and timing every request by the route it matched,
+/// requests that matched no route (the frontend files) are grouped under `fallback`
+pub async fn track<B>(request: Request<B>, next: Next<B>) -> Response {
+    let route = request.extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "fallback".to_string(), |path| path.as_str().to_string());
+    let start = Instant::now();
+
+    let response = next.run(request).await;
+
+    REQUEST_SECONDS.with_label_values(&[&route])
+        .observe(start.elapsed().as_secs_f64());
+    REQUESTS.with_label_values(&[&route, response.status().as_str()])
+        .inc();
+
+    response
+}
+
+/// handler for "/metrics", in the prometheus text format
+#[allow(clippy::unused_async, clippy::cast_possible_wrap)]
+pub async fn metrics() -> Result<impl IntoResponse> {
+    let pool = POOL.status();
+    POOL_WORKERS.set(pool.workers as i64);
+    POOL_RUNNING.set(pool.running as i64);
+    POOL_QUEUED.set(pool.queued as i64);
+    POOL_CAPACITY.set(pool.capacity as i64);
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    encoder.encode(&prometheus::gather(), &mut buffer)
+        .map_err(|err| ImageError::Internal(err.to_string()))?;
+
+    Ok((
+        [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+        buffer,
+    ))
+}
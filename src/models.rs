@@ -1,14 +1,17 @@
 use serde::{Serialize, Deserialize};
+use crate::output::OutputFormat;
 
 /// used for `lego` and `mc` endpoints to indicate how many blocks to use for the image
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct SizeOption {
     /// size (max number of blocks for a side) for generated image
-    pub size: Option<u8>,
+    pub size: Option<u32>,
 }
 
 /// used for `paint` function
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct PaintOption {
     /// radius of paint strokes
     pub radius: Option<i32>,
@@ -18,33 +21,39 @@ pub struct PaintOption {
 
 /// used for `braille` function
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct BrailleOption {
     /// threshold to determine fill or empty
     pub threshold: Option<u8>,
     /// indicates whether to invert pixel values or not
     pub invert: Option<bool>,
     /// size (max length of a side) for generated image
-    pub size: Option<u16>,
+    pub size: Option<u32>,
 }
 
 /// used for `ascii` function
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct AsciiOption {
     /// indicates whether to invert pixel values or not
     pub invert: Option<bool>,
     /// size (max length of a side) for generated image
-    pub size: Option<u16>,
+    pub size: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct MatrixOption {
     /// size (max number of blocks for a side) for generated image
-    pub size: Option<u8>,
+    pub size: Option<u32>,
     /// specifies whether or not to use numbers only
     pub num_only: Option<bool>,
+    /// seeds the characters picked, for the same output every time
+    pub seed: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ShapesOption {
     /// size of each individual shape rendered
     pub block: Option<u8>,
@@ -52,15 +61,47 @@ pub struct ShapesOption {
     pub density: Option<u32>,
     /// specifies whether to make it animated or not
     pub gif: Option<bool>,
+    /// seeds the placement of the shapes, for the same output every time
+    pub seed: Option<u32>,
 }
 
 /// used for `black_white`
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct SmoothOption {
     /// indicates whether or not to have smooth pixels
     pub smooth: Option<bool>,
 }
 
+/// accepted by every endpoint alongside its own options,
+/// to choose how the output image is encoded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormatOption {
+    /// output format, takes precedence over the `Accept` header
+    pub format: Option<OutputFormat>,
+    /// quality (1 - 100) for lossy formats, defaults to 90
+    pub quality: Option<u8>,
+}
+
+/// how animated input images are processed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameMode {
+    /// every frame is processed, keeping the animation
+    All,
+    /// only the first frame is processed
+    First,
+}
+
+/// accepted by every endpoint alongside its own options,
+/// to choose how animated input images are handled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrameOption {
+    /// defaults to processing every frame
+    pub frames: Option<FrameMode>,
+}
+
 /// an empty struct used in endpoints with no query arguments to accept
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct NoArgs {}
\ No newline at end of file
@@ -0,0 +1,695 @@
+//! module generating the `OpenAPI 3` document of the app from the [`REGISTRY`] of effects,
+//! served at "/openapi.json" and rendered by the docs page at "/docs"
+
+use axum::{response::Html, Json};
+use serde_json::{json, Map, Value};
+
+use image_web::{
+    effect::{EffectInfo, OutputKind, Parameter},
+    effects::REGISTRY,
+    output::OutputFormat,
+};
+
+use crate::{routes, settings};
+
+/// converts the schema of an effect option into an `OpenAPI` query parameter
+fn query_parameter(parameter: &Parameter) -> Value {
+    let mut schema = json!({
+        "type": parameter.kind,
+    });
+
+    // options without a default, such as `seed`, are left out rather than given a `null` one
+    if !parameter.default.is_null() {
+        schema["default"] = parameter.default.clone();
+    }
+    if let Some(minimum) = &parameter.minimum {
+        schema["minimum"] = minimum.clone();
+    }
+    if let Some(maximum) = &parameter.maximum {
+        schema["maximum"] = maximum.clone();
+    }
+
+    json!({
+        "name": parameter.name,
+        "in": "query",
+        "required": false,
+        "description": parameter.description,
+        "schema": schema,
+    })
+}
+
+/// the multipart upload of an effect route, the image and optionally its options,
+/// either as a JSON `options` part or as their own form fields
+fn upload_body(effect: &EffectInfo) -> Value {
+    let options = effect.options
+        .iter()
+        .map(|parameter| (parameter.name.to_string(), query_parameter(parameter)["schema"].clone()))
+        .collect::<Map<String, Value>>();
+
+    let mut properties = options.clone();
+    properties.extend(common_fields());
+    properties.insert("image".to_string(), json!({ "type": "string", "format": "binary" }));
+    properties.insert("options".to_string(), json!({
+        "type": "object",
+        "description": "The options as JSON, taking precedence over the query string. \
+            giving an option both here and as its own form field is an `invalid_fields` error. \
+            `format`, `quality` and `frames` are not accepted here, see their own fields.",
+        "properties": options,
+        "additionalProperties": false,
+    }));
+
+    json!({
+        "required": true,
+        "content": {
+            "multipart/form-data": {
+                "schema": {
+                    "type": "object",
+                    "required": ["image"],
+                    "properties": properties,
+                },
+                "encoding": { "options": { "contentType": "application/json" } },
+            },
+        },
+    })
+}
+
+/// the query parameters accepted by every processing route,
+/// see [`image_web::models::FormatOption`] and [`image_web::models::FrameOption`]
+fn common_parameters() -> Vec<Value> {
+    vec![
+        json!({ "$ref": "#/components/parameters/format" }),
+        json!({ "$ref": "#/components/parameters/quality" }),
+        json!({ "$ref": "#/components/parameters/frames" }),
+    ]
+}
+
+/// the query parameters of the [`COMMON_FIELDS`](crate::wrapper::COMMON_FIELDS), under `components`
+fn common_definitions() -> Value {
+    json!({
+        "format": {
+            "name": "format",
+            "in": "query",
+            "required": false,
+            "description": "Output format, takes precedence over the `Accept` header",
+            "schema": {
+                "type": "string",
+                "enum": OutputFormat::enabled()
+                    .flat_map(|format| format.names().iter().copied())
+                    .collect::<Vec<_>>(),
+            },
+        },
+        "quality": {
+            "name": "quality",
+            "in": "query",
+            "required": false,
+            "description": "Quality of lossy formats",
+            "schema": { "type": "integer", "minimum": 1, "maximum": 100, "default": 90 },
+        },
+        "frames": {
+            "name": "frames",
+            "in": "query",
+            "required": false,
+            "description": "Whether to process every frame of an animated image or only the first",
+            "schema": { "type": "string", "enum": ["all", "first"], "default": "all" },
+        },
+    })
+}
+
+/// the [`COMMON_FIELDS`](crate::wrapper::COMMON_FIELDS) as the properties of a multipart upload
+fn common_fields() -> Map<String, Value> {
+    common_definitions()
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(name, parameter)| {
+            let mut schema = parameter["schema"].clone();
+            schema["description"] = json!(format!(
+                "{}, taking precedence over the query string",
+                parameter["description"].as_str().unwrap_or_default(),
+            ));
+            (name.clone(), schema)
+        })
+        .collect()
+}
+
+/// the responses shared by every processing route
+fn responses() -> Value {
+    let content = OutputFormat::enabled()
+        .map(|format| (
+            format.mime_type().to_string(),
+            json!({ "schema": { "type": "string", "format": "binary" } }),
+        ))
+        .collect::<Map<String, Value>>();
+
+    json!({
+        "200": {
+            "description": "The processed image, encoded in the negotiated format",
+            "content": content,
+        },
+        "400": { "$ref": "#/components/responses/BadRequest" },
+        "406": { "$ref": "#/components/responses/NotAcceptable" },
+        "413": { "$ref": "#/components/responses/PayloadTooLarge" },
+        "415": { "$ref": "#/components/responses/UnsupportedMediaType" },
+        "422": { "$ref": "#/components/responses/UnprocessableEntity" },
+        "500": { "$ref": "#/components/responses/InternalError" },
+        "503": { "$ref": "#/components/responses/ServiceUnavailable" },
+        "504": { "$ref": "#/components/responses/GatewayTimeout" },
+    })
+}
+
+/// describes the `POST` operation of a single effect
+fn effect_path(effect: &EffectInfo) -> Value {
+    let mut parameters = effect.options
+        .iter()
+        .map(query_parameter)
+        .collect::<Vec<Value>>();
+    parameters.extend(common_parameters());
+    parameters.push(json!({
+        "name": "If-None-Match",
+        "in": "header",
+        "required": false,
+        "description": "`ETag` of a previous response, cacheable outputs are not sent again if it matches",
+        "schema": { "type": "string" },
+    }));
+
+    let mut responses = responses();
+    responses["200"]["headers"] = json!({
+        "ETag": {
+            "description": "Hash of the upload and options, only sent for cacheable outputs",
+            "schema": { "type": "string" },
+        },
+    });
+    responses["304"] = json!({ "description": "The output matches the `ETag` given in `If-None-Match`" });
+
+    json!({
+        "post": {
+            "operationId": effect.name,
+            "summary": effect.description,
+            "tags": ["effects"],
+            "description": match effect.output {
+                OutputKind::Static => "Produces a still image.",
+                OutputKind::Animated => "Produces an animated image by default.",
+            },
+            "parameters": parameters,
+            "requestBody": upload_body(effect),
+            "responses": responses,
+        }
+    })
+}
+
+/// describes the `POST` operation submitting a single effect as a job
+fn job_path(effect: &EffectInfo) -> Value {
+    let mut parameters = effect.options
+        .iter()
+        .map(query_parameter)
+        .collect::<Vec<Value>>();
+    parameters.extend(common_parameters());
+
+    json!({
+        "post": {
+            "operationId": format!("job_{}", effect.name),
+            "summary": format!("Submits `{}` as a job", effect.name),
+            "tags": ["jobs"],
+            "parameters": parameters,
+            "requestBody": upload_body(effect),
+            "responses": {
+                "202": {
+                    "description": "The job was accepted, its status is served at the `Location`",
+                    "headers": { "Location": { "schema": { "type": "string" } } },
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "id": { "type": "string" },
+                                    "location": { "type": "string" },
+                                },
+                            },
+                        },
+                    },
+                },
+                "400": { "$ref": "#/components/responses/BadRequest" },
+                "413": { "$ref": "#/components/responses/PayloadTooLarge" },
+                "422": { "$ref": "#/components/responses/UnprocessableEntity" },
+                "503": { "$ref": "#/components/responses/ServiceUnavailable" },
+            },
+        }
+    })
+}
+
+/// converts a path of the router into an `OpenAPI` path, `/jobs/:id` into `/jobs/{id}`
+fn openapi_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(name) => format!("{{{name}}}"),
+            None => segment.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// the operations of the routes besides the effects, referenced by the route table in [`crate::routes`]
+pub mod operations {
+    use serde_json::{json, Value};
+
+    use super::{common_fields, common_parameters, responses};
+
+    /// the `id` path parameter of the job routes
+    fn id() -> Value {
+        json!({
+            "name": "id",
+            "in": "path",
+            "required": true,
+            "schema": { "type": "string" },
+        })
+    }
+
+    /// `POST /pipeline`
+    pub fn pipeline() -> Value {
+        let mut properties = common_fields();
+        properties.insert("image".to_string(), json!({ "type": "string", "format": "binary" }));
+        properties.insert("steps".to_string(), json!({
+            "type": "array",
+            "items": { "$ref": "#/components/schemas/Step" },
+        }));
+
+        json!({
+            "operationId": "pipeline",
+            "summary": "Chains several effects on one image",
+            "tags": ["pipeline"],
+            "parameters": common_parameters(),
+            "requestBody": {
+                "required": true,
+                "content": {
+                    "multipart/form-data": {
+                        "schema": {
+                            "type": "object",
+                            "required": ["image", "steps"],
+                            "properties": properties,
+                        },
+                        "encoding": { "steps": { "contentType": "application/json" } },
+                    },
+                },
+            },
+            "responses": responses(),
+        })
+    }
+
+    /// `GET /effects`
+    pub fn effects() -> Value {
+        json!({
+            "operationId": "effects",
+            "summary": "Lists every effect with the schema of its options",
+            "tags": ["discovery"],
+            "responses": {
+                "200": {
+                    "description": "Every effect, those whose assets failed or are still loading are not `available`",
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "array",
+                                "items": { "$ref": "#/components/schemas/Effect" },
+                            },
+                        },
+                    },
+                },
+            },
+        })
+    }
+
+    /// `GET /openapi.json`
+    pub fn openapi() -> Value {
+        json!({
+            "operationId": "openapi",
+            "summary": "Serves this document",
+            "tags": ["discovery"],
+            "responses": {
+                "200": {
+                    "description": "The `OpenAPI` document of the server",
+                    "content": { "application/json": { "schema": { "type": "object" } } },
+                },
+            },
+        })
+    }
+
+    /// `GET /queue`
+    pub fn queue() -> Value {
+        json!({
+            "operationId": "queue",
+            "summary": "Reports how busy the workers processing images are",
+            "tags": ["discovery"],
+            "responses": {
+                "200": {
+                    "description": "The state of the worker pool",
+                    "content": {
+                        "application/json": {
+                            "schema": { "$ref": "#/components/schemas/Queue" },
+                        },
+                    },
+                },
+            },
+        })
+    }
+
+    /// `GET /metrics`
+    pub fn metrics() -> Value {
+        json!({
+            "operationId": "metrics",
+            "summary": "Exposes the metrics of the server in the prometheus text format",
+            "tags": ["discovery"],
+            "responses": {
+                "200": {
+                    "description": "The current value of every metric",
+                    "content": {
+                        "text/plain": {
+                            "schema": { "type": "string" },
+                        },
+                    },
+                },
+            },
+        })
+    }
+
+    /// `GET /healthz`
+    pub fn healthz() -> Value {
+        json!({
+            "operationId": "healthz",
+            "summary": "Succeeds as long as the server is up",
+            "tags": ["health"],
+            "responses": {
+                "200": {
+                    "description": "The server is up",
+                    "content": {
+                        "application/json": {
+                            "schema": { "$ref": "#/components/schemas/Health" },
+                        },
+                    },
+                },
+            },
+        })
+    }
+
+    /// `GET /readyz`
+    pub fn readyz() -> Value {
+        json!({
+            "operationId": "readyz",
+            "summary": "Succeeds once every asset used by the effects has loaded",
+            "description": "Fails as long as any asset failed to load, listing it in `failed`. The reason of each failure is reported by `/assets`.",
+            "tags": ["health"],
+            "responses": {
+                "200": {
+                    "description": "Every asset has loaded",
+                    "content": {
+                        "application/json": {
+                            "schema": { "$ref": "#/components/schemas/Health" },
+                        },
+                    },
+                },
+                "503": {
+                    "description": "The assets are still loading, or some of them failed to load",
+                    "content": {
+                        "application/json": {
+                            "schema": { "$ref": "#/components/schemas/Health" },
+                        },
+                    },
+                },
+            },
+        })
+    }
+
+    /// `GET /assets`
+    pub fn assets() -> Value {
+        json!({
+            "operationId": "assets",
+            "summary": "Reports which assets used by the effects loaded and which failed",
+            "tags": ["health"],
+            "responses": {
+                "200": {
+                    "description": "The status of every asset, keyed on its path",
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "additionalProperties": { "$ref": "#/components/schemas/Asset" },
+                            },
+                        },
+                    },
+                },
+            },
+        })
+    }
+
+    /// `GET /jobs/{id}`
+    pub fn job_status() -> Value {
+        json!({
+            "operationId": "job_status",
+            "summary": "Reports the status of a job",
+            "tags": ["jobs"],
+            "parameters": [id()],
+            "responses": {
+                "200": {
+                    "description": "The status of the job",
+                    "content": {
+                        "application/json": {
+                            "schema": { "$ref": "#/components/schemas/Job" },
+                        },
+                    },
+                },
+                "404": { "$ref": "#/components/responses/NotFound" },
+            },
+        })
+    }
+
+    /// `GET /jobs/{id}/events`
+    pub fn job_events() -> Value {
+        json!({
+            "operationId": "job_events",
+            "summary": "Streams the progress of a job as server sent events",
+            "description": "Sends `progress` events carrying the stage and percentage of the job, \
+                ending with a `done` event carrying the location of the result or a `failed` event carrying the error.",
+            "tags": ["jobs"],
+            "parameters": [id()],
+            "responses": {
+                "200": {
+                    "description": "The stream of events",
+                    "content": { "text/event-stream": { "schema": { "type": "string" } } },
+                },
+                "404": { "$ref": "#/components/responses/NotFound" },
+            },
+        })
+    }
+
+    /// `GET /jobs/{id}/result`
+    pub fn job_result() -> Value {
+        let mut responses = responses();
+        responses["409"] = json!({ "$ref": "#/components/responses/Conflict" });
+        responses["404"] = json!({ "$ref": "#/components/responses/NotFound" });
+
+        json!({
+            "operationId": "job_result",
+            "summary": "Serves the processed image of a finished job, or the error it failed with",
+            "tags": ["jobs"],
+            "parameters": [id()],
+            "responses": responses,
+        })
+    }
+}
+
+/// builds the `OpenAPI` document from the route table,
+/// the routes of the effects in the [`REGISTRY`] and the other ones in [`routes::table`]
+pub fn document() -> Value {
+    let effects = REGISTRY.iter()
+        .map(|effect| effect.info(&settings::get().effects))
+        .collect::<Vec<_>>();
+
+    let mut paths = effects
+        .iter()
+        .map(|effect| (effect.route.clone(), effect_path(effect)))
+        .chain(effects.iter().map(|effect| (format!("/jobs/{}", effect.name), job_path(effect))))
+        .collect::<Map<String, Value>>();
+
+    for endpoint in routes::table() {
+        if let Some(operation) = endpoint.operation {
+            paths.entry(openapi_path(endpoint.path))
+                .or_insert_with(|| json!({}))[endpoint.method] = operation();
+        }
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": env!("CARGO_PKG_NAME"),
+            "description": env!("CARGO_PKG_DESCRIPTION"),
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": components(&effects),
+    })
+}
+
+/// the reusable parameters, bodies, responses and schemas referenced by the paths
+fn components(effects: &[EffectInfo]) -> Value {
+    let parameters = common_definitions();
+
+    let error = |description: &str, codes: &[&str]| json!({
+        "description": description,
+        "content": {
+            "application/json": {
+                "schema": {
+                    "allOf": [
+                        { "$ref": "#/components/schemas/Error" },
+                        { "properties": { "code": { "enum": codes } } },
+                    ],
+                },
+            },
+        },
+    });
+
+    let mut responses = json!({
+        "BadRequest": error("The upload is malformed or could not be decoded", &["corrupt_image", "missing_field", "bad_request"]),
+        "NotAcceptable": error("The output cannot be encoded in any of the requested formats", &["not_acceptable"]),
+        "PayloadTooLarge": error("The upload exceeds the size, frame or pixel limits", &["too_large"]),
+        "UnsupportedMediaType": error("The upload is not in a supported image format", &["unsupported_format"]),
+        "UnprocessableEntity": error(
+            "The options are invalid, `invalid_fields` lists every invalid option as a `FieldError` in `details.fields`",
+            &["invalid_option", "invalid_fields"],
+        ),
+        "InternalError": error("The image could not be processed", &["internal"]),
+        "ServiceUnavailable": error(
+            "Every worker is busy and the queue is full, the assets are still loading, or the effect is unavailable",
+            &["overloaded", "cancelled", "warming_up", "unavailable"],
+        ),
+        "NotFound": error("The job does not exist or has expired", &["not_found"]),
+        "Conflict": error("The job has not finished yet", &["not_ready"]),
+        "GatewayTimeout": error("Processing took longer than the timeout of the effect", &["timed_out"]),
+    });
+    responses["ServiceUnavailable"]["headers"] = json!({
+        "Retry-After": {
+            "description": "Seconds to wait before retrying",
+            "schema": { "type": "integer" },
+        },
+    });
+
+    let option = json!({
+        "type": "object",
+        "properties": {
+            "name": { "type": "string" },
+            "type": { "type": "string", "enum": ["integer", "number", "boolean"] },
+            "description": { "type": "string" },
+            "default": {},
+            "minimum": { "type": "number" },
+            "maximum": { "type": "number" },
+        },
+    });
+
+    let schemas = json!({
+        "Error": {
+            "type": "object",
+            "required": ["code", "message", "details"],
+            "properties": {
+                "code": { "type": "string" },
+                "message": { "type": "string" },
+                "details": { "type": "object", "nullable": true },
+            },
+        },
+        "FieldError": {
+            "type": "object",
+            "required": ["field", "message"],
+            "properties": {
+                "field": { "type": "string" },
+                "message": { "type": "string" },
+                "minimum": { "type": "number" },
+                "maximum": { "type": "number" },
+            },
+        },
+        "Step": {
+            "type": "object",
+            "required": ["effect"],
+            "properties": {
+                "effect": {
+                    "type": "string",
+                    "enum": effects.iter().map(|effect| effect.name).collect::<Vec<&str>>(),
+                },
+                "options": { "type": "object" },
+            },
+        },
+        "Job": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" },
+                "effect": { "type": "string" },
+                "status": {
+                    "type": "string",
+                    "enum": ["queued", "decoding", "processing", "encoding", "done", "failed"],
+                },
+                "percent": { "type": "integer", "minimum": 0, "maximum": 100 },
+                "events": { "type": "string" },
+                "result": { "type": "string", "nullable": true },
+                "error": {
+                    "type": "object",
+                    "nullable": true,
+                    "properties": {
+                        "code": { "type": "string" },
+                        "message": { "type": "string" },
+                    },
+                },
+            },
+        },
+        "Queue": {
+            "type": "object",
+            "properties": {
+                "workers": { "type": "integer" },
+                "running": { "type": "integer" },
+                "queued": { "type": "integer" },
+                "capacity": { "type": "integer" },
+            },
+        },
+        "Health": {
+            "type": "object",
+            "properties": {
+                "status": { "type": "string", "enum": ["ok", "ready", "not_ready"] },
+                "failed": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "The assets that failed to load, if any",
+                },
+            },
+        },
+        "Asset": {
+            "type": "object",
+            "properties": {
+                "status": { "type": "string", "enum": ["pending", "loaded", "failed"] },
+                "error": { "type": "string" },
+            },
+        },
+        "Effect": {
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "route": { "type": "string" },
+                "description": { "type": "string" },
+                "output": { "type": "string", "enum": ["static", "animated"] },
+                "options": { "type": "array", "items": option },
+                "available": { "type": "boolean" },
+            },
+        },
+    });
+
+    json!({
+        "parameters": parameters,
+        "responses": responses,
+        "schemas": schemas,
+    })
+}
+
+/// handler for "/openapi.json"
+#[allow(clippy::unused_async)]
+pub async fn openapi() -> Json<Value> {
+    Json(document())
+}
+
+/// handler for "/docs", rendering the `OpenAPI` document without any external script
+#[allow(clippy::unused_async)]
+pub async fn docs() -> Html<&'static str> {
+    Html(include_str!("../frontend/docs.html"))
+}
@@ -0,0 +1,407 @@
+//! module containing the [`Output`] type produced by the processing functions
+//! and the logic for negotiating and encoding it into the bytes sent back to the client
+
+use ril::{encodings::jpeg::JpegEncoder, prelude::*, Encoder};
+use serde::{Serialize, Deserialize};
+
+use crate::error::{Error, Result};
+
+/// quality used for lossy encodings when none is requested
+const DEFAULT_QUALITY: u8 = 90;
+
+/// the result of a processing function,
+/// either a single still image or an animated sequence of frames
+pub enum Output {
+    /// a single still image
+    Static(Image<Rgba>),
+    /// an animated sequence of frames, carrying its own frame delays and loop count
+    Animated(ImageSequence<Rgba>),
+}
+
+impl From<Image<Rgba>> for Output {
+    fn from(image: Image<Rgba>) -> Self {
+        Self::Static(image)
+    }
+}
+
+impl From<ImageSequence<Rgba>> for Output {
+    fn from(sequence: ImageSequence<Rgba>) -> Self {
+        Self::Animated(sequence)
+    }
+}
+
+/// the formats an [`Output`] can be encoded into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// `PNG`, or `APNG` for animations
+    #[serde(alias = "apng")]
+    Png,
+    /// `JPEG`, still images only
+    #[serde(alias = "jpg")]
+    Jpeg,
+    /// `WebP`, only available when compiled with the `webp` feature
+    #[serde(rename = "webp")]
+    WebP,
+    /// `GIF`
+    Gif,
+}
+
+impl OutputFormat {
+    /// every format, in order of preference when the client accepts any
+    pub const ALL: [Self; 4] = [Self::Png, Self::Gif, Self::WebP, Self::Jpeg];
+
+    /// every format that was compiled in, in the order of [`ALL`](Self::ALL).
+    /// this is what should be advertised to clients, `webp` being left out without the `webp` feature
+    pub fn enabled() -> impl Iterator<Item = Self> {
+        Self::ALL
+            .into_iter()
+            .filter(|format| format.is_enabled())
+    }
+
+    /// returns `true` if this format was compiled in
+    pub const fn is_enabled(self) -> bool {
+        match self {
+            Self::WebP => cfg!(feature = "webp"),
+            Self::Png | Self::Jpeg | Self::Gif => true,
+        }
+    }
+
+    /// every name the format is parsed from, its extension first
+    pub const fn names(self) -> &'static [&'static str] {
+        match self {
+            Self::Png => &["png", "apng"],
+            Self::Jpeg => &["jpg", "jpeg"],
+            Self::WebP => &["webp"],
+            Self::Gif => &["gif"],
+        }
+    }
+
+    /// the mime type sent in the `Content-Type` header for this format
+    pub const fn mime_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::WebP => "image/webp",
+            Self::Gif => "image/gif",
+        }
+    }
+
+    /// the file extension of this format
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::WebP => "webp",
+            Self::Gif => "gif",
+        }
+    }
+
+    /// maps a mime type from an `Accept` header to a format
+    pub fn from_mime_type(mime: &str) -> Option<Self> {
+        match mime.to_ascii_lowercase().as_str() {
+            "image/png" | "image/apng" => Some(Self::Png),
+            "image/jpeg" | "image/jpg" => Some(Self::Jpeg),
+            "image/webp" => Some(Self::WebP),
+            "image/gif" => Some(Self::Gif),
+            _ => None,
+        }
+    }
+
+    /// returns `true` if this format was compiled in and can represent
+    /// the output, `animated` indicating whether it holds more than one frame
+    pub const fn supports(self, animated: bool) -> bool {
+        match self {
+            Self::Png | Self::Gif => true,
+            Self::Jpeg => !animated,
+            Self::WebP => self.is_enabled(),
+        }
+    }
+
+    /// the format used when the client has no preference
+    pub const fn default_for(animated: bool) -> Self {
+        if animated { Self::Gif } else { Self::Png }
+    }
+
+    /// picks the format to encode the output in.
+    ///
+    /// an `explicit` format (from the `format` query parameter) always takes precedence,
+    /// otherwise the `accept` header is consulted in order of its quality values,
+    /// with `image/*` and `*/*` resolving to the default format.
+    ///
+    /// returns [`None`] if none of the requested formats can represent the output
+    pub fn negotiate(explicit: Option<Self>, accept: Option<&str>, animated: bool) -> Option<Self> {
+        if let Some(format) = explicit {
+            return format.supports(animated)
+                .then_some(format);
+        }
+
+        let accept = match accept {
+            Some(accept) if !accept.trim().is_empty() => accept,
+            _ => return Some(Self::default_for(animated)),
+        };
+
+        let mut ranges = accept
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let mime = parts.next()?.trim();
+                let quality = parts
+                    .filter_map(|param| param.trim().strip_prefix("q="))
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+
+                (quality > 0.0).then_some((mime, quality))
+            })
+            .collect::<Vec<(&str, f32)>>();
+
+        // stable sort, so equally weighted ranges keep the client's order
+        ranges.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+        ranges
+            .into_iter()
+            .find_map(|(mime, _)| match mime {
+                "*/*" | "image/*" => Some(Self::default_for(animated)),
+                mime => Self::from_mime_type(mime)
+                    .filter(|format| format.supports(animated)),
+            })
+    }
+}
+
+impl Output {
+    /// applies `function` to a decoded input `sequence`.
+    ///
+    /// with `per_frame` set, functions producing a still image are applied to every frame,
+    /// building an animation that keeps the delays, disposal methods and loop count of the input.
+    /// single frame inputs only receive their first frame, and so do functions that animate on their own:
+    /// if the first frame of the input yields an animation, that animation is the output
+    /// and the other frames of the input are dropped.
+    ///
+    /// fails with [`Error::TooLarge`] as soon as the frames of the output add up to more than `max_pixels`,
+    /// before the next frame is processed
+    pub fn map_frames<F, E>(
+        sequence: ImageSequence<Rgba>,
+        per_frame: bool,
+        max_pixels: u64,
+        mut function: F,
+    ) -> std::result::Result<Self, E>
+    where
+        F: FnMut(Image<Rgba>) -> std::result::Result<Self, E>,
+        E: From<Error>,
+    {
+        if !per_frame || sequence.len() <= 1 {
+            let output = function(sequence.into_first_image())?;
+            output.check_pixels(max_pixels)?;
+            return Ok(output);
+        }
+
+        let mut output = ImageSequence::<Rgba>::new()
+            .with_loop_count(sequence.loop_count());
+        let mut pixels = 0_u64;
+
+        for frame in sequence {
+            let (delay, disposal) = (frame.delay(), frame.disposal());
+
+            let image = match function(frame.into_image())? {
+                Self::Static(image) => image,
+                animated @ Self::Animated(_) if output.is_empty() => {
+                    animated.check_pixels(max_pixels)?;
+                    return Ok(animated);
+                }
+                animated @ Self::Animated(_) => animated.into_first_image(),
+            };
+
+            pixels = pixels.saturating_add(image_pixels(&image));
+            check_pixels(pixels, max_pixels)?;
+
+            output.push_frame(
+                Frame::from_image(image)
+                    .with_delay(delay)
+                    .with_disposal(disposal)
+            );
+        }
+
+        Ok(Self::Animated(output))
+    }
+
+    /// the total amount of pixels over every frame of the output
+    pub fn pixels(&self) -> u64 {
+        match self {
+            Self::Static(image) => image_pixels(image),
+            Self::Animated(sequence) => sequence.iter()
+                .map(|frame| image_pixels(frame.image()))
+                .fold(0, u64::saturating_add),
+        }
+    }
+
+    /// fails with [`Error::TooLarge`] if the frames of the output add up to more than `max_pixels`
+    pub fn check_pixels(&self, max_pixels: u64) -> Result<()> {
+        check_pixels(self.pixels(), max_pixels)
+    }
+
+    /// consumes the output, returning its only or first frame
+    pub fn into_first_image(self) -> Image<Rgba> {
+        match self {
+            Self::Static(image) => image,
+            Self::Animated(sequence) => sequence.into_first_image(),
+        }
+    }
+
+    /// returns `true` if the output holds more than a single frame
+    pub fn is_animated(&self) -> bool {
+        matches!(self, Self::Animated(sequence) if sequence.len() > 1)
+    }
+
+    /// encodes the output into `format`, keeping every frame if it is animated.
+    ///
+    /// `quality` only applies to lossy formats (`JPEG`), ranging from 1 to 100.
+    /// the format is expected to [`support`](OutputFormat::supports) the output
+    pub fn encode(self, format: OutputFormat, quality: Option<u8>) -> ril::Result<Vec<u8>> {
+        let mut bytes = Vec::<u8>::new();
+
+        match (self, format) {
+            (Self::Static(image), OutputFormat::Jpeg) => {
+                JpegEncoder::new()
+                    .with_quality(quality.unwrap_or(DEFAULT_QUALITY))
+                    .encode(&image.convert::<Rgb>(), &mut bytes)?;
+            }
+            (output @ Self::Animated(_), OutputFormat::Jpeg) => {
+                JpegEncoder::new()
+                    .with_quality(quality.unwrap_or(DEFAULT_QUALITY))
+                    .encode(&output.into_first_image().convert::<Rgb>(), &mut bytes)?;
+            }
+            (Self::Static(image), format) => {
+                image.encode(format.into(), &mut bytes)?;
+            }
+            (Self::Animated(sequence), format) if sequence.len() > 1 => {
+                sequence.encode(format.into(), &mut bytes)?;
+            }
+            (Self::Animated(sequence), format) => {
+                sequence.into_first_image()
+                    .encode(format.into(), &mut bytes)?;
+            }
+        }
+
+        Ok(bytes)
+    }
+}
+
+/// the amount of pixels of a single image
+fn image_pixels(image: &Image<Rgba>) -> u64 {
+    let (width, height) = image.dimensions();
+    u64::from(width) * u64::from(height)
+}
+
+/// fails with [`Error::TooLarge`] if `pixels` exceeds `max_pixels`
+fn check_pixels(pixels: u64, max_pixels: u64) -> Result<()> {
+    if pixels > max_pixels {
+        return Err(Error::TooLarge {
+            unit: "pixels",
+            actual: pixels,
+            limit: max_pixels,
+        });
+    }
+
+    Ok(())
+}
+
+impl From<OutputFormat> for ImageFormat {
+    fn from(format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::Png => Self::Png,
+            OutputFormat::Jpeg => Self::Jpeg,
+            OutputFormat::WebP => Self::WebP,
+            OutputFormat::Gif => Self::Gif,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// an image of `size` by `size` transparent pixels
+    fn image(size: u32) -> Image<Rgba> {
+        Image::new(size, size, Rgba::transparent())
+    }
+
+    /// an animated sequence of `frames` images of `size` by `size`
+    fn sequence(frames: usize, size: u32) -> ImageSequence<Rgba> {
+        let mut sequence = ImageSequence::<Rgba>::new();
+        for _ in 0..frames {
+            sequence.push_frame(Frame::from_image(image(size)));
+        }
+        sequence
+    }
+
+    #[test]
+    fn every_frame_is_mapped() {
+        let output = Output::map_frames(sequence(3, 4), true, u64::MAX, |image| Ok::<_, Error>(image.into()));
+
+        assert!(matches!(output, Ok(Output::Animated(sequence)) if sequence.len() == 3));
+    }
+
+    #[test]
+    fn only_the_first_frame_is_mapped_without_per_frame() {
+        let mut calls = 0;
+        let output = Output::map_frames(sequence(3, 4), false, u64::MAX, |image| {
+            calls += 1;
+            Ok::<_, Error>(image.into())
+        });
+
+        assert!(matches!(output, Ok(Output::Static(_))));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn growing_output_fails_before_the_next_frame() {
+        let mut calls = 0;
+        let output = Output::map_frames(sequence(5, 2), true, 250, |_| {
+            calls += 1;
+            Ok::<_, Error>(image(10).into())
+        });
+
+        assert!(matches!(output, Err(Error::TooLarge { unit: "pixels", actual: 300, limit: 250 })));
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn animation_of_the_first_frame_replaces_the_input() {
+        let mut calls = 0;
+        let output = Output::map_frames(sequence(4, 4), true, u64::MAX, |_| {
+            calls += 1;
+            Ok::<_, Error>(sequence(2, 4).into())
+        });
+
+        assert!(matches!(output, Ok(Output::Animated(sequence)) if sequence.len() == 2));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn animation_of_a_still_input_is_checked() {
+        let output = Output::map_frames(sequence(1, 4), true, 200, |_| Ok::<_, Error>(sequence(3, 10).into()));
+
+        assert!(matches!(output, Err(Error::TooLarge { actual: 300, .. })));
+    }
+
+    #[test]
+    fn pixels_add_up_over_every_frame() {
+        assert_eq!(Output::from(image(3)).pixels(), 9);
+        assert_eq!(Output::from(sequence(4, 3)).pixels(), 36);
+    }
+
+    #[test]
+    fn every_name_parses_to_its_format() {
+        for format in OutputFormat::ALL {
+            for name in format.names() {
+                assert_eq!(serde_json::from_str::<OutputFormat>(&format!("\"{name}\"")).ok(), Some(format));
+            }
+            assert_eq!(format.names()[0], format.extension());
+        }
+    }
+
+    #[test]
+    fn webp_is_only_enabled_with_its_feature() {
+        assert_eq!(OutputFormat::enabled().any(|format| format == OutputFormat::WebP), cfg!(feature = "webp"));
+    }
+}
@@ -0,0 +1,193 @@
+//! module containing the `/pipeline` route,
+//! which chains several processing functions on a single upload
+
+use std::collections::HashMap;
+use axum::{
+    extract::Query,
+    http::{header, HeaderMap},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+use image_web::{
+    config::EffectsConfig,
+    context::{Context, Stage},
+    effects::REGISTRY,
+    error::{Error as ImageError, FieldError},
+    output::{Output, OutputFormat},
+};
+
+use crate::{
+    health::{self, ASSETS},
+    metrics::{self, Stage as Timed},
+    pool::{self, POOL},
+    response::{self, Error, MultipartResult, QueryResult, Result},
+    settings,
+    wrapper,
+};
+
+/// a single step of a pipeline, naming one of the effects in the [`REGISTRY`],
+/// for example `{"effect": "lego", "options": {"size": 20}}`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Step {
+    /// name of the processing function, the same as its route
+    pub effect: String,
+    /// options for the function, the same as its query arguments
+    #[serde(default)]
+    pub options: Value,
+}
+
+/// checks the assets and options of every step before any processing happens,
+/// failing with [`ImageError::InvalidFields`] naming the invalid options as `steps[{index}].{option}`
+fn check_steps(steps: &[Step], config: &EffectsConfig) -> Result<()> {
+    for (index, Step { effect, options }) in steps.iter().enumerate() {
+        let effect = REGISTRY.get(effect)
+            .ok_or_else(|| ImageError::InvalidOption(format!("unknown effect in pipeline: {effect}")))?;
+
+        health::check(effect.assets())?;
+        effect.validate(options, config)
+            .map_err(|err| match err {
+                ImageError::InvalidFields(fields) => ImageError::InvalidFields(
+                    fields.into_iter()
+                        .map(|field| FieldError {
+                            field: format!("steps[{index}].{}", field.field),
+                            ..field
+                        })
+                        .collect()
+                ),
+                err => err,
+            })?;
+    }
+
+    Ok(())
+}
+
+/// runs every step in order, each on the output of the previous one.
+///
+/// each step is bounded by the total pixel limit of `context` as its output is built,
+/// and an animated output is checked against the frame count limit before the next step starts,
+/// since effects such as `lines` turn a still image into many frames
+pub fn run(mut output: Output, steps: Vec<Step>, config: &EffectsConfig, context: &Context) -> Result<Output> {
+    let max_frames = settings::get().limits.max_frames;
+
+    for Step { effect, options } in steps {
+        context.check()?;
+        if let Output::Animated(sequence) = &output {
+            wrapper::check_frame_limits(sequence, max_frames, context.max_pixels())?;
+        }
+
+        output = REGISTRY.get(&effect)
+            .ok_or_else(|| ImageError::InvalidOption(format!("unknown effect in pipeline: {effect}")))?
+            .apply_json(output, options, &ASSETS, config, context)?;
+    }
+
+    if let Output::Animated(sequence) = &output {
+        wrapper::check_frame_limits(sequence, max_frames, context.max_pixels())?;
+    }
+
+    Ok(output)
+}
+
+/// handler for "/pipeline"
+///
+/// takes a multipart upload with the image bytes and a `steps` field,
+/// holding an ordered JSON list of [`Step`]s that are all run as one job on the [`POOL`].
+/// a second image fails with [`Error::BadRequest`], like the effect routes.
+/// the [`COMMON_FIELDS`](wrapper::COMMON_FIELDS) may be given as form fields as well as in the query string
+pub async fn pipeline(
+    query: QueryResult,
+    headers: HeaderMap,
+    multipart: MultipartResult,
+) -> Result<impl IntoResponse> {
+    let Query(query) = query.map_err(response::query)?;
+    let mut multipart = multipart.map_err(response::upload)?;
+    let accept = wrapper::accept_header(&headers);
+
+    let (mut buffer, mut steps, mut form) = (None, None, HashMap::new());
+
+    while let Some(field) = multipart.next_field().await.map_err(response::multipart)? {
+        let name = field.name().unwrap_or_default().to_string();
+        let common = field.file_name().is_none() && wrapper::COMMON_FIELDS.contains(&name.as_str());
+
+        if common {
+            wrapper::read_form_field(field, &mut form).await?;
+        } else if name == "steps" {
+            if steps.is_some() {
+                return Err(Error::BadRequest("the steps part is given more than once".to_string()));
+            }
+
+            let bytes = wrapper::read_field(field).await?;
+
+            steps = Some(
+                serde_json::from_slice::<Vec<Step>>(&bytes)
+                    .map_err(|err| ImageError::InvalidOption(format!("invalid pipeline steps: {err}")))?
+            );
+        } else if buffer.is_none() {
+            buffer = Some(wrapper::read_field(field).await?);
+        } else {
+            return Err(Error::BadRequest(format!("unexpected multipart field {name}, only one image can be uploaded")));
+        }
+    }
+
+    let buffer = buffer.ok_or(Error::MissingField("image bytes"))?;
+    let steps = steps.ok_or(Error::MissingField("steps"))?;
+    let (format, frames) = wrapper::common_options(&query, &form)?;
+
+    let max_steps = settings::get().limits.max_pipeline_steps;
+    if steps.len() > max_steps {
+        return Err(ImageError::TooLarge {
+            unit: "steps",
+            actual: steps.len() as u64,
+            limit: max_steps as u64,
+        }.into());
+    }
+
+    check_steps(&steps, &settings::get().effects)?;
+
+    let context = Context::new()
+        .with_max_pixels(settings::get().limits.max_total_pixels);
+    let _cancel = context.cancel_on_drop();
+    let job = context.clone();
+
+    let timeout = settings::get().limits.timeout_for("pipeline");
+    let (output_format, bytes) = pool::with_timeout(&context, timeout, POOL.run(
+        move || -> Result<(OutputFormat, Vec<u8>)> {
+            let _span = tracing::info_span!(
+                "process",
+                effect = "pipeline",
+                steps = ?steps,
+            ).entered();
+
+            job.check()?;
+            job.set_stage(Stage::Decoding);
+            let timer = metrics::time_stage("pipeline", Timed::Decode);
+            let (sequence, per_frame) = wrapper::decode(&buffer, &frames)?;
+            drop(timer);
+
+            job.set_stage(Stage::Processing);
+            let timer = metrics::time_stage("pipeline", Timed::Effect);
+            let output = Output::map_frames(
+                sequence,
+                per_frame,
+                job.max_pixels(),
+                |image| Ok::<_, Error>(Output::Static(image)),
+            )?;
+            let output = run(output, steps, &settings::get().effects, &job)?;
+            drop(timer);
+
+            job.set_stage(Stage::Encoding);
+            let _timer = metrics::time_stage("pipeline", Timed::Encode);
+            wrapper::encode(output, &format, accept.as_deref())
+        }
+    ))
+        .await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, output_format.mime_type()),
+            (header::VARY, "Accept"),
+        ],
+        bytes,
+    ))
+}
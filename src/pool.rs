@@ -0,0 +1,195 @@
+//! module containing the [`Pool`] every image processing job runs on,
+//! limiting how many run at once and how many may wait for a worker
+
+use std::{
+    future::Future,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+use axum::Json;
+use serde::Serialize;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use image_web::{context::Context, error::Error as ImageError};
+
+use crate::{
+    response::{self, Error, Result},
+    settings::{self, PoolConfig},
+};
+
+lazy_static::lazy_static! {
+    /// the pool shared by every processing route, sized by the config
+    pub static ref POOL: Pool = Pool::new(&settings::get().pool);
+}
+
+/// a fixed amount of workers running blocking jobs,
+/// in front of a bounded queue of jobs waiting for one of them
+pub struct Pool {
+    /// one permit per worker
+    workers: Semaphore,
+    /// amount of workers, the total permits of `workers`
+    size: usize,
+    /// maximum amount of jobs waiting for a worker
+    capacity: usize,
+    /// seconds clients are told to wait when the queue is full
+    retry_after: u64,
+    /// amount of jobs waiting for a worker
+    queued: AtomicUsize,
+    /// amount of jobs currently running
+    running: AtomicUsize,
+}
+
+/// a snapshot of the state of the [`Pool`], served by "/queue"
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PoolStatus {
+    /// amount of workers
+    pub workers: usize,
+    /// amount of jobs currently running
+    pub running: usize,
+    /// amount of jobs waiting for a worker
+    pub queued: usize,
+    /// maximum amount of jobs waiting for a worker
+    pub capacity: usize,
+}
+
+/// decrements a counter of the pool when dropped,
+/// so it stays accurate when a request is cancelled while waiting
+pub struct Slot<'a>(&'a AtomicUsize);
+
+impl<'a> Slot<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Self(counter)
+    }
+
+    /// increments the counter only if it is below `capacity`,
+    /// returning its current value otherwise
+    fn reserve(counter: &'a AtomicUsize, capacity: usize) -> std::result::Result<Self, usize> {
+        counter.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| (count < capacity).then_some(count + 1))
+            .map(|_| Self(counter))
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Pool {
+    pub fn new(config: &PoolConfig) -> Self {
+        Self {
+            workers: Semaphore::new(config.workers),
+            size: config.workers,
+            capacity: config.queue,
+            retry_after: config.retry_after,
+            queued: AtomicUsize::new(0),
+            running: AtomicUsize::new(0),
+        }
+    }
+
+    /// claims a worker, or a place in the queue waiting for one,
+    /// failing with [`Error::Overloaded`] if the queue is already full
+    pub fn reserve(&'static self) -> Result<Ticket> {
+        if let Ok(permit) = self.workers.try_acquire() {
+            return Ok(Ticket::Ready(self, permit));
+        }
+
+        Slot::reserve(&self.queued, self.capacity)
+            .map(|slot| Ticket::Queued(self, slot))
+            .map_err(|queued| Error::Overloaded {
+                queued: queued as u64,
+                capacity: self.capacity as u64,
+                retry_after: self.retry_after,
+            })
+    }
+
+    /// runs `job` on a blocking thread once a worker is free,
+    /// failing with [`Error::Overloaded`] right away if the queue is already full.
+    pub async fn run<T, F>(&'static self, job: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T> + Send + 'static,
+    {
+        self.reserve()?
+            .run(job)
+            .await
+    }
+
+    /// the current state of the pool
+    pub fn status(&self) -> PoolStatus {
+        PoolStatus {
+            workers: self.size,
+            running: self.running.load(Ordering::SeqCst),
+            queued: self.queued.load(Ordering::SeqCst),
+            capacity: self.capacity,
+        }
+    }
+}
+
+/// a worker or a place in the queue of the [`Pool`], claimed by [`Pool::reserve`]
+pub enum Ticket {
+    /// a worker was free
+    Ready(&'static Pool, SemaphorePermit<'static>),
+    /// waiting for a worker
+    Queued(&'static Pool, Slot<'static>),
+}
+
+impl Ticket {
+    /// runs `job` on a blocking thread, waiting for a worker first if needed.
+    ///
+    /// the worker is held by the job itself,
+    /// so it is only released once the job finishes even if the request is cancelled.
+    /// the job runs in the current span, so its logs stay attached to the request
+    pub async fn run<T, F>(self, job: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T> + Send + 'static,
+    {
+        let (pool, permit) = match self {
+            Self::Ready(pool, permit) => (pool, permit),
+            Self::Queued(pool, slot) => {
+                let permit = pool.workers.acquire()
+                    .await
+                    .map_err(|err| ImageError::Internal(err.to_string()))?;
+
+                drop(slot);
+                (pool, permit)
+            }
+        };
+
+        let running = Slot::new(&pool.running);
+        let span = tracing::Span::current();
+        tokio::task::spawn_blocking(move || {
+            let _worker = (permit, running);
+            span.in_scope(job)
+        })
+            .await
+            .map_err(response::join)?
+    }
+}
+
+/// awaits `future`, usually a job of the [`POOL`], until `timeout` passes,
+/// cancelling `context` so that the job stops and failing with [`Error::TimedOut`] if it does
+pub async fn with_timeout<T>(
+    context: &Context,
+    timeout: Option<Duration>,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(timeout) = timeout else {
+        return future.await;
+    };
+
+    if let Ok(result) = tokio::time::timeout(timeout, future).await {
+        result
+    } else {
+        context.cancel();
+        Err(Error::TimedOut { seconds: timeout.as_secs() })
+    }
+}
+
+/// handler for "/queue", reporting the state of the [`POOL`]
+#[allow(clippy::unused_async)]
+pub async fn queue() -> Json<PoolStatus> {
+    Json(POOL.status())
+}
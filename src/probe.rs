@@ -0,0 +1,568 @@
+//! module for reading the dimensions and frame count of an uploaded image
+//! from its headers alone, so oversized images can be rejected before any pixels are decoded.
+//!
+//! every offset is computed with `checked_add`, the lengths read from the headers are untrusted
+
+/// the dimensions and frame count of an image, as declared by its headers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Probe {
+    /// width of the image (or of its largest frame)
+    pub width: u32,
+    /// height of the image (or of its largest frame)
+    pub height: u32,
+    /// amount of frames, 1 for still images
+    pub frames: u64,
+}
+
+impl Probe {
+    /// the amount of pixels in a single frame
+    pub fn pixels(&self) -> u64 {
+        u64::from(self.width) * u64::from(self.height)
+    }
+
+    /// the amount of pixels summed over every frame
+    pub fn total_pixels(&self) -> u64 {
+        self.pixels()
+            .saturating_mul(self.frames)
+    }
+}
+
+/// the `len` bytes at `at`, if they are all there
+fn slice(bytes: &[u8], at: usize, len: usize) -> Option<&[u8]> {
+    bytes.get(at..at.checked_add(len)?)
+}
+
+/// reads a big endian `u16` at `at`
+fn u16_be(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(slice(bytes, at, 2)?.try_into().ok()?))
+}
+
+/// reads a little endian `u16` at `at`
+fn u16_le(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(slice(bytes, at, 2)?.try_into().ok()?))
+}
+
+/// reads a little endian 24 bit integer at `at`
+fn u24_le(bytes: &[u8], at: usize) -> Option<u32> {
+    let bytes = slice(bytes, at, 3)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]))
+}
+
+/// reads a big endian `u32` at `at`
+fn u32_be(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(slice(bytes, at, 4)?.try_into().ok()?))
+}
+
+/// reads a little endian `u32` at `at`
+fn u32_le(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(slice(bytes, at, 4)?.try_into().ok()?))
+}
+
+/// probes a `PNG`, counting the frames declared by the `acTL` chunk of an `APNG`
+fn png(bytes: &[u8]) -> Option<Probe> {
+    // `IHDR` is always the first chunk, right after the signature
+    if slice(bytes, 12, 4)? != b"IHDR" {
+        return None;
+    }
+
+    let width = u32_be(bytes, 16)?;
+    let height = u32_be(bytes, 20)?;
+    let mut frames = 1;
+
+    // `acTL` has to appear before the first `IDAT`
+    let mut offset = 8;
+    while let (Some(length), Some(kind)) = (u32_be(bytes, offset), slice(bytes, offset.checked_add(4)?, 4)) {
+        let data = offset.checked_add(8)?;
+
+        match kind {
+            b"acTL" => {
+                frames = u64::from(u32_be(bytes, data)?);
+                break;
+            }
+            b"IDAT" | b"IEND" => break,
+            _ => {}
+        }
+
+        // the crc follows the chunk data
+        offset = data
+            .checked_add(usize::try_from(length).ok()?)?
+            .checked_add(4)?;
+    }
+
+    Some(Probe { width, height, frames })
+}
+
+/// size in bytes of a `GIF` color table, given the flags of the block it belongs to
+fn gif_color_table_size(flags: u8) -> usize {
+    if flags & 0x80 == 0 { 0 } else { 3 << ((flags & 0x07) + 1) }
+}
+
+/// skips a chain of `GIF` data sub blocks, returning the offset after its terminator
+fn gif_skip_sub_blocks(bytes: &[u8], mut offset: usize) -> Option<usize> {
+    loop {
+        let size = usize::from(*bytes.get(offset)?);
+        offset = offset.checked_add(1 + size)?;
+
+        if size == 0 {
+            return Some(offset);
+        }
+    }
+}
+
+/// probes a `GIF`, walking its blocks to count the image descriptors (frames)
+fn gif(bytes: &[u8]) -> Option<Probe> {
+    let mut width = u32::from(u16_le(bytes, 6)?);
+    let mut height = u32::from(u16_le(bytes, 8)?);
+    let mut frames = 0;
+
+    let mut offset = 13 + gif_color_table_size(*bytes.get(10)?);
+
+    // truncated files are still decoded, so count whatever frames are present
+    while let Some(&block) = bytes.get(offset) {
+        match block {
+            // image descriptor, frames can be larger than the logical screen
+            0x2C => {
+                frames += 1;
+                width = width.max(u32::from(u16_le(bytes, offset.checked_add(5)?)?));
+                height = height.max(u32::from(u16_le(bytes, offset.checked_add(7)?)?));
+
+                // descriptor, local color table and the LZW minimum code size
+                let flags = *bytes.get(offset.checked_add(9)?)?;
+                let data = offset
+                    .checked_add(11)?
+                    .checked_add(gif_color_table_size(flags))?;
+                match gif_skip_sub_blocks(bytes, data) {
+                    Some(next) => offset = next,
+                    None => break,
+                }
+            }
+            // extension, introducer and label followed by sub blocks
+            0x21 => match gif_skip_sub_blocks(bytes, offset.checked_add(2)?) {
+                Some(next) => offset = next,
+                None => break,
+            },
+            // trailer
+            0x3B => break,
+            _ => return None,
+        }
+    }
+
+    Some(Probe { width, height, frames: frames.max(1) })
+}
+
+/// probes a `JPEG`, reading the dimensions from its start of frame segment
+fn jpeg(bytes: &[u8]) -> Option<Probe> {
+    let mut offset = 2;
+
+    loop {
+        if *bytes.get(offset)? != 0xFF {
+            return None;
+        }
+
+        offset = match *bytes.get(offset.checked_add(1)?)? {
+            // fill bytes
+            0xFF => offset.checked_add(1)?,
+            // start of frame, excluding `DHT`, `JPG` and `DAC` which share the range
+            marker @ 0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                return Some(Probe {
+                    width: u32::from(u16_be(bytes, offset.checked_add(7)?)?),
+                    height: u32::from(u16_be(bytes, offset.checked_add(5)?)?),
+                    frames: 1,
+                });
+            }
+            // standalone markers without a length
+            0x01 | 0xD0..=0xD7 => offset.checked_add(2)?,
+            // start of scan or end of image before any frame header
+            0xD9 | 0xDA => return None,
+            _ => offset
+                .checked_add(2)?
+                .checked_add(usize::from(u16_be(bytes, offset.checked_add(2)?)?))?,
+        };
+    }
+}
+
+/// probes a `WebP`, reading the canvas of an extended file and counting its `ANMF` frames
+fn webp(bytes: &[u8]) -> Option<Probe> {
+    let mut dimensions = None;
+    let mut frames = 0;
+
+    let mut offset = 12;
+    while let (Some(kind), Some(size)) = (slice(bytes, offset, 4), u32_le(bytes, offset.checked_add(4)?)) {
+        let data = offset.checked_add(8)?;
+
+        match kind {
+            b"VP8X" => dimensions = Some((
+                u24_le(bytes, data.checked_add(4)?)? + 1,
+                u24_le(bytes, data.checked_add(7)?)? + 1,
+            )),
+            b"VP8 " if dimensions.is_none() => dimensions = Some((
+                u32::from(u16_le(bytes, data.checked_add(6)?)? & 0x3FFF),
+                u32::from(u16_le(bytes, data.checked_add(8)?)? & 0x3FFF),
+            )),
+            b"VP8L" if dimensions.is_none() => {
+                let bits = u32_le(bytes, data.checked_add(1)?)?;
+                dimensions = Some((
+                    (bits & 0x3FFF) + 1,
+                    ((bits >> 14) & 0x3FFF) + 1,
+                ));
+            }
+            b"ANMF" => frames += 1,
+            _ => {}
+        }
+
+        // chunks are padded to an even size
+        let size = usize::try_from(size).ok()?;
+        offset = data
+            .checked_add(size)?
+            .checked_add(size & 1)?;
+    }
+
+    let (width, height) = dimensions?;
+    Some(Probe { width, height, frames: frames.max(1) })
+}
+
+/// reads the dimensions and frame count of `bytes` from their headers.
+///
+/// returns `Ok(None)` if the format is not one that can be probed,
+/// and an error if it is but its headers are malformed
+pub fn probe(bytes: &[u8]) -> Result<Option<Probe>, &'static str> {
+    let (probe, err) = if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        (png(bytes), "the PNG header is malformed")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        (gif(bytes), "the GIF header is malformed")
+    } else if bytes.starts_with(&[0xFF, 0xD8]) {
+        (jpeg(bytes), "the JPEG header is malformed")
+    } else if bytes.starts_with(b"RIFF") && slice(bytes, 8, 4) == Some(&b"WEBP"[..]) {
+        (webp(bytes), "the WebP header is malformed")
+    } else {
+        return Ok(None);
+    };
+
+    probe.map(Some)
+        .ok_or(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a `PNG` chunk with a zeroed crc, which is not checked
+    fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = u32::try_from(data.len()).unwrap().to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    /// a `PNG` of `width` by `height` made of `chunks` after its `IHDR`
+    fn png_file(width: u32, height: u32, chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut header = width.to_be_bytes().to_vec();
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+        let mut bytes = b"\x89PNG\r\n\x1a\n".to_vec();
+        bytes.extend(png_chunk(b"IHDR", &header));
+        for chunk in chunks {
+            bytes.extend_from_slice(chunk);
+        }
+        bytes
+    }
+
+    /// an `acTL` chunk declaring `frames`
+    fn actl(frames: u32) -> Vec<u8> {
+        let mut data = frames.to_be_bytes().to_vec();
+        data.extend_from_slice(&0_u32.to_be_bytes());
+        png_chunk(b"acTL", &data)
+    }
+
+    #[test]
+    fn png_dimensions() {
+        let bytes = png_file(640, 480, &[png_chunk(b"IDAT", &[0; 8]), png_chunk(b"IEND", &[])]);
+
+        assert_eq!(probe(&bytes), Ok(Some(Probe { width: 640, height: 480, frames: 1 })));
+    }
+
+    #[test]
+    fn apng_frames() {
+        let bytes = png_file(32, 16, &[png_chunk(b"tEXt", b"a comment"), actl(24), png_chunk(b"IDAT", &[0; 8])]);
+
+        assert_eq!(probe(&bytes), Ok(Some(Probe { width: 32, height: 16, frames: 24 })));
+    }
+
+    #[test]
+    fn png_actl_after_idat_is_ignored() {
+        let bytes = png_file(32, 16, &[png_chunk(b"IDAT", &[0; 8]), actl(24)]);
+
+        assert_eq!(probe(&bytes).unwrap().unwrap().frames, 1);
+    }
+
+    #[test]
+    fn png_truncated() {
+        let bytes = png_file(640, 480, &[]);
+
+        assert!(probe(&bytes[..20]).is_err());
+        assert!(probe(&bytes[..8]).is_err());
+        // an `acTL` cut before its frame count
+        let bytes = png_file(640, 480, &[actl(24)]);
+        assert!(probe(&bytes[..bytes.len() - 10]).is_err());
+    }
+
+    #[test]
+    fn png_huge_chunk_length() {
+        let mut chunk = png_chunk(b"tEXt", &[]);
+        chunk[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+        let bytes = png_file(640, 480, &[chunk, actl(24)]);
+
+        assert_eq!(probe(&bytes), Ok(Some(Probe { width: 640, height: 480, frames: 1 })));
+    }
+
+    #[test]
+    fn png_without_ihdr() {
+        let mut bytes = png_file(640, 480, &[]);
+        bytes[12..16].copy_from_slice(b"tEXt");
+
+        assert!(probe(&bytes).is_err());
+    }
+
+    /// a `GIF` image descriptor of `width` by `height` with a local color table of 4 colors and some data
+    fn gif_frame(width: u16, height: u16) -> Vec<u8> {
+        let mut frame = vec![0x2C, 0, 0, 0, 0];
+        frame.extend_from_slice(&width.to_le_bytes());
+        frame.extend_from_slice(&height.to_le_bytes());
+        frame.push(0x81);
+        frame.extend_from_slice(&[0; 12]);
+        // LZW minimum code size, then two sub blocks
+        frame.extend_from_slice(&[2, 3, 1, 2, 3, 1, 4, 0]);
+        frame
+    }
+
+    /// a `GIF` with a logical screen of `width` by `height`, a global color table of 2 colors and `blocks`
+    fn gif_file(width: u16, height: u16, blocks: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = b"GIF89a".to_vec();
+        bytes.extend_from_slice(&width.to_le_bytes());
+        bytes.extend_from_slice(&height.to_le_bytes());
+        bytes.extend_from_slice(&[0x80, 0, 0]);
+        bytes.extend_from_slice(&[0; 6]);
+        for block in blocks {
+            bytes.extend_from_slice(block);
+        }
+        bytes
+    }
+
+    /// a graphic control extension
+    fn gif_extension() -> Vec<u8> {
+        vec![0x21, 0xF9, 4, 0, 10, 0, 0, 0]
+    }
+
+    #[test]
+    fn gif_frames() {
+        let bytes = gif_file(100, 50, &[
+            b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00".to_vec(),
+            gif_extension(),
+            gif_frame(100, 50),
+            gif_extension(),
+            gif_frame(120, 40),
+            vec![0x3B],
+        ]);
+
+        assert_eq!(probe(&bytes), Ok(Some(Probe { width: 120, height: 50, frames: 2 })));
+    }
+
+    #[test]
+    fn gif_without_frames() {
+        let bytes = gif_file(100, 50, &[vec![0x3B]]);
+
+        assert_eq!(probe(&bytes), Ok(Some(Probe { width: 100, height: 50, frames: 1 })));
+    }
+
+    #[test]
+    fn gif_truncated() {
+        let bytes = gif_file(100, 50, &[gif_frame(100, 50), gif_frame(100, 50)]);
+
+        // cut inside the data of the second frame, which is still counted
+        assert_eq!(probe(&bytes[..bytes.len() - 3]).unwrap().unwrap().frames, 2);
+        // cut inside the descriptor of the second frame
+        assert!(probe(&bytes[..bytes.len() - 25]).is_err());
+        // cut inside the logical screen descriptor
+        assert!(probe(&bytes[..9]).is_err());
+    }
+
+    #[test]
+    fn gif_unknown_block() {
+        let bytes = gif_file(100, 50, &[gif_frame(100, 50), vec![0x42]]);
+
+        assert!(probe(&bytes).is_err());
+    }
+
+    /// a `JPEG` segment with its length
+    fn jpeg_segment(marker: u8, data: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xFF, marker];
+        segment.extend_from_slice(&u16::try_from(data.len() + 2).unwrap().to_be_bytes());
+        segment.extend_from_slice(data);
+        segment
+    }
+
+    /// a baseline start of frame of `width` by `height`
+    fn sof(width: u16, height: u16) -> Vec<u8> {
+        let mut data = vec![8];
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&[1, 1, 0x11, 0]);
+        jpeg_segment(0xC0, &data)
+    }
+
+    fn jpeg_file(segments: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = vec![0xFF, 0xD8];
+        for segment in segments {
+            bytes.extend_from_slice(segment);
+        }
+        bytes
+    }
+
+    #[test]
+    fn jpeg_sof_after_app_segments() {
+        let bytes = jpeg_file(&[
+            jpeg_segment(0xE0, b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0"),
+            jpeg_segment(0xE1, &[0; 300]),
+            jpeg_segment(0xC4, &[0; 20]),
+            vec![0xFF, 0xFF],
+            sof(1920, 1080),
+            jpeg_segment(0xDA, &[0; 10]),
+        ]);
+
+        assert_eq!(probe(&bytes), Ok(Some(Probe { width: 1920, height: 1080, frames: 1 })));
+    }
+
+    #[test]
+    fn jpeg_scan_before_sof() {
+        let bytes = jpeg_file(&[jpeg_segment(0xE0, &[0; 14]), jpeg_segment(0xDA, &[0; 10]), sof(1920, 1080)]);
+
+        assert!(probe(&bytes).is_err());
+    }
+
+    #[test]
+    fn jpeg_truncated() {
+        let bytes = jpeg_file(&[jpeg_segment(0xE1, &[0; 300]), sof(1920, 1080)]);
+
+        assert!(probe(&bytes[..bytes.len() - 8]).is_err());
+        assert!(probe(&bytes[..100]).is_err());
+        assert!(probe(&bytes[..3]).is_err());
+    }
+
+    #[test]
+    fn jpeg_garbage_between_segments() {
+        let mut bytes = jpeg_file(&[jpeg_segment(0xE0, &[0; 14])]);
+        bytes.push(0x00);
+        bytes.extend(sof(1920, 1080));
+
+        assert!(probe(&bytes).is_err());
+    }
+
+    /// a `RIFF` chunk, padded to an even size
+    fn webp_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = kind.to_vec();
+        chunk.extend_from_slice(&u32::try_from(data.len()).unwrap().to_le_bytes());
+        chunk.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn webp_file(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body = chunks.concat();
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&u32::try_from(body.len() + 4).unwrap().to_le_bytes());
+        bytes.extend_from_slice(b"WEBP");
+        bytes.extend(body);
+        bytes
+    }
+
+    /// a lossy bitstream of `width` by `height`
+    fn vp8(width: u16, height: u16) -> Vec<u8> {
+        let mut data = vec![0x10, 0x02, 0x00, 0x9D, 0x01, 0x2A];
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data.extend_from_slice(&[0; 7]);
+        webp_chunk(b"VP8 ", &data)
+    }
+
+    /// a lossless bitstream of `width` by `height`
+    fn vp8l(width: u32, height: u32) -> Vec<u8> {
+        let mut data = vec![0x2F];
+        data.extend_from_slice(&((width - 1) | (height - 1) << 14).to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        webp_chunk(b"VP8L", &data)
+    }
+
+    /// an extended header with a canvas of `width` by `height`
+    fn vp8x(width: u32, height: u32) -> Vec<u8> {
+        let mut data = vec![0x02, 0, 0, 0];
+        data.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+        data.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+        webp_chunk(b"VP8X", &data)
+    }
+
+    #[test]
+    fn webp_lossy() {
+        let bytes = webp_file(&[vp8(300, 200)]);
+
+        assert_eq!(probe(&bytes), Ok(Some(Probe { width: 300, height: 200, frames: 1 })));
+    }
+
+    #[test]
+    fn webp_lossless() {
+        let bytes = webp_file(&[vp8l(300, 200)]);
+
+        assert_eq!(probe(&bytes), Ok(Some(Probe { width: 300, height: 200, frames: 1 })));
+    }
+
+    #[test]
+    fn webp_extended_animation() {
+        let bytes = webp_file(&[
+            vp8x(1000, 800),
+            webp_chunk(b"ANIM", &[0; 6]),
+            webp_chunk(b"ANMF", &[0; 17]),
+            webp_chunk(b"ANMF", &[0; 17]),
+            webp_chunk(b"ANMF", &[0; 17]),
+        ]);
+
+        assert_eq!(probe(&bytes), Ok(Some(Probe { width: 1000, height: 800, frames: 3 })));
+    }
+
+    #[test]
+    fn webp_extended_canvas_takes_precedence() {
+        let bytes = webp_file(&[vp8x(1000, 800), webp_chunk(b"ICCP", &[0; 3]), vp8(300, 200)]);
+
+        assert_eq!(probe(&bytes), Ok(Some(Probe { width: 1000, height: 800, frames: 1 })));
+    }
+
+    #[test]
+    fn webp_truncated() {
+        let bytes = webp_file(&[vp8(300, 200)]);
+
+        assert!(probe(&bytes[..bytes.len() - 10]).is_err());
+        assert!(probe(&bytes[..16]).is_err());
+    }
+
+    #[test]
+    fn webp_huge_chunk_size() {
+        let mut chunk = webp_chunk(b"ICCP", &[]);
+        chunk[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        let bytes = webp_file(&[vp8x(1000, 800), chunk, vp8(300, 200)]);
+
+        assert_eq!(probe(&bytes), Ok(Some(Probe { width: 1000, height: 800, frames: 1 })));
+        // without any dimensions before it
+        let mut chunk = webp_chunk(b"ICCP", &[]);
+        chunk[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(probe(&webp_file(&[chunk, vp8(300, 200)])).is_err());
+    }
+
+    #[test]
+    fn unknown_formats_are_not_probed() {
+        assert_eq!(probe(b"BM\0\0\0\0"), Ok(None));
+        assert_eq!(probe(b""), Ok(None));
+        assert_eq!(probe(b"RIFF\0\0\0\0WAVE"), Ok(None));
+    }
+}
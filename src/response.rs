@@ -0,0 +1,210 @@
+//! module containing the [`Error`] of the server, which wraps the [`ImageError`] of the library
+//! next to the errors of the requests, jobs and pool around the processing,
+//! and renders them all as a JSON body with their stable code, message and details.
+//!
+//! the extractors of the processing routes are taken as [`QueryResult`] and [`MultipartResult`],
+//! so that their rejections are rendered the same way instead of as plain text
+
+use std::{collections::HashMap, fmt};
+use axum::{
+    extract::{
+        multipart::MultipartError,
+        rejection::{MultipartRejection, QueryRejection},
+        Multipart,
+        Query,
+    },
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{json, Value};
+use tokio::task::JoinError;
+
+use image_web::{error::Error as ImageError, output::OutputFormat};
+
+use crate::jobs::JobStatus;
+
+/// every error a request can fail with
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// processing the image failed
+    Image(ImageError),
+    /// a required multipart field is missing
+    MissingField(&'static str),
+    /// the request body is malformed
+    BadRequest(String),
+    /// the requested resource does not exist (or has expired)
+    NotFound(&'static str),
+    /// the result of a job was requested before it finished
+    NotReady(JobStatus),
+    /// the output cannot be encoded in any of the formats requested
+    NotAcceptable {
+        animated: bool,
+    },
+    /// every worker is busy and the queue of waiting requests is full
+    Overloaded {
+        queued: u64,
+        capacity: u64,
+        retry_after: u64,
+    },
+    /// processing took longer than the timeout of the effect, in seconds
+    TimedOut {
+        seconds: u64,
+    },
+    /// the assets of the effects are still loading
+    WarmingUp,
+}
+
+/// shortcut typealias for results of the handlers and everything they call
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// the query string of a request, or why it could not be parsed, see [`query`]
+pub type QueryResult = std::result::Result<Query<HashMap<String, String>>, QueryRejection>;
+
+/// the multipart body of a request, or why it is not one, see [`upload`]
+pub type MultipartResult = std::result::Result<Multipart, MultipartRejection>;
+
+impl Error {
+    /// the stable, machine readable code of the error
+    pub const fn code(&self) -> &'static str {
+        match self {
+            Self::Image(err) => err.code(),
+            Self::MissingField(_) => "missing_field",
+            Self::BadRequest(_) => "bad_request",
+            Self::NotFound(_) => "not_found",
+            Self::NotReady(_) => "not_ready",
+            Self::NotAcceptable { .. } => "not_acceptable",
+            Self::Overloaded { .. } => "overloaded",
+            Self::TimedOut { .. } => "timed_out",
+            Self::WarmingUp => "warming_up",
+        }
+    }
+
+    /// extra structured information about the error, if any
+    pub fn details(&self) -> Option<Value> {
+        match self {
+            Self::Image(err) => err.details(),
+            Self::MissingField(field) => Some(json!({ "field": field })),
+            Self::NotReady(status) => Some(json!({ "status": status })),
+            Self::NotAcceptable { animated } => Some(json!({
+                "available": OutputFormat::enabled()
+                    .filter(|format| format.supports(*animated))
+                    .map(OutputFormat::mime_type)
+                    .collect::<Vec<&str>>(),
+            })),
+            Self::Overloaded { queued, capacity, retry_after } => Some(json!({
+                "queued": queued,
+                "capacity": capacity,
+                "retry_after": retry_after,
+            })),
+            Self::TimedOut { seconds } => Some(json!({ "timeout": seconds })),
+            _ => None,
+        }
+    }
+
+    /// the HTTP status code the error is responded with
+    pub const fn status(&self) -> StatusCode {
+        match self {
+            Self::Image(err) => match err {
+                ImageError::UnsupportedFormat => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                ImageError::CorruptImage(_) => StatusCode::BAD_REQUEST,
+                ImageError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+                ImageError::InvalidOption(_) | ImageError::InvalidFields(_) => StatusCode::UNPROCESSABLE_ENTITY,
+                ImageError::Cancelled | ImageError::Unavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+                ImageError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            Self::MissingField(_) | Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::NotReady(_) => StatusCode::CONFLICT,
+            Self::NotAcceptable { .. } => StatusCode::NOT_ACCEPTABLE,
+            Self::Overloaded { .. } | Self::WarmingUp => StatusCode::SERVICE_UNAVAILABLE,
+            Self::TimedOut { .. } => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+
+    /// logs the error in the current span,
+    /// as an error if the server is at fault and as a warning otherwise
+    pub fn log(&self) {
+        if self.status().is_server_error() {
+            tracing::error!(code = self.code(), message = %self, "request failed");
+        } else {
+            tracing::warn!(code = self.code(), message = %self, "request failed");
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Image(err) => fmt::Display::fmt(err, f),
+            Self::MissingField(field) => write!(f, "Missing required multipart field for {field}"),
+            Self::BadRequest(err) => write!(f, "The request is malformed: {err}"),
+            Self::NotFound(resource) => write!(f, "The {resource} does not exist or has expired"),
+            Self::NotReady(_) => write!(f, "The job has not finished yet"),
+            Self::NotAcceptable { animated } => write!(
+                f,
+                "The {} output of this endpoint cannot be encoded in the requested format",
+                if *animated { "animated" } else { "static" },
+            ),
+            Self::Overloaded { retry_after, .. } =>
+                write!(f, "The server is busy processing other images, retry in {retry_after} seconds"),
+            Self::TimedOut { seconds } =>
+                write!(f, "Processing the image took longer than the limit of {seconds} seconds"),
+            Self::WarmingUp => write!(f, "The server is still loading the assets of the effects, retry in a moment"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ImageError> for Error {
+    fn from(err: ImageError) -> Self {
+        Self::Image(err)
+    }
+}
+
+/// a malformed multipart body, as an [`Error::BadRequest`]
+#[allow(clippy::needless_pass_by_value)]
+pub fn multipart(err: MultipartError) -> Error {
+    Error::BadRequest(err.to_string())
+}
+
+/// a query string that could not be parsed, as an [`ImageError::InvalidOption`]
+#[allow(clippy::needless_pass_by_value)]
+pub fn query(rejection: QueryRejection) -> Error {
+    ImageError::InvalidOption(format!("the query string could not be parsed: {rejection}")).into()
+}
+
+/// a request that is not a multipart upload, such as one without a boundary, as an [`Error::BadRequest`]
+#[allow(clippy::needless_pass_by_value)]
+pub fn upload(rejection: MultipartRejection) -> Error {
+    Error::BadRequest(rejection.to_string())
+}
+
+/// a blocking task that panicked or was cancelled, as an [`ImageError::Internal`]
+#[allow(clippy::needless_pass_by_value)]
+pub fn join(err: JoinError) -> Error {
+    ImageError::Internal(err.to_string()).into()
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        self.log();
+
+        let mut response = (
+            self.status(),
+            Json(json!({
+                "code": self.code(),
+                "message": self.to_string(),
+                "details": self.details(),
+            })),
+        ).into_response();
+
+        if let Self::Overloaded { retry_after, .. } = self {
+            response.headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+
+        response
+    }
+}
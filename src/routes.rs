@@ -0,0 +1,129 @@
+//! module holding the route table of the server:
+//! the routes of every effect of the library and the [`table`] of the other ones,
+//! which the `OpenAPI` document is generated from
+
+use std::sync::Arc;
+use axum::{
+    body::Body,
+    handler::Handler,
+    http::HeaderMap,
+    routing::{self, post, MethodRouter},
+    Json,
+    Router,
+};
+use image_web::{
+    effect::{Effect, EffectInfo, Register},
+    effects::{self, REGISTRY},
+};
+use serde_json::Value;
+
+use crate::{
+    health,
+    jobs,
+    metrics,
+    openapi::{self, operations},
+    pipeline,
+    pool,
+    response::{MultipartResult, QueryResult},
+    root,
+    settings,
+    wrapper,
+};
+
+/// a route of the server besides the effects, see [`table`]
+pub struct Endpoint {
+    /// path of the route, with the `:param` syntax of the router
+    pub path: &'static str,
+    /// method of the route, lowercase as in the `OpenAPI` document
+    pub method: &'static str,
+    /// handler of the route
+    pub handler: MethodRouter<Body>,
+    /// the `OpenAPI` operation of the route, `None` for the pages that are not part of the API
+    pub operation: Option<fn() -> Value>,
+}
+
+impl Endpoint {
+    /// a `GET` route
+    fn get<H: Handler<T, Body>, T: 'static>(path: &'static str, handler: H, operation: Option<fn() -> Value>) -> Self {
+        Self { path, method: "get", handler: routing::get(handler), operation }
+    }
+
+    /// a `POST` route
+    fn post<H: Handler<T, Body>, T: 'static>(path: &'static str, handler: H, operation: Option<fn() -> Value>) -> Self {
+        Self { path, method: "post", handler: routing::post(handler), operation }
+    }
+}
+
+/// every route of the server besides the effects
+pub fn table() -> Vec<Endpoint> {
+    vec![
+        Endpoint::get("/", root, None),
+        Endpoint::get("/docs", openapi::docs, None),
+        Endpoint::get("/openapi.json", openapi::openapi, Some(operations::openapi)),
+        Endpoint::post("/pipeline", pipeline::pipeline, Some(operations::pipeline)),
+        Endpoint::get("/effects", list, Some(operations::effects)),
+        Endpoint::get("/queue", pool::queue, Some(operations::queue)),
+        Endpoint::get("/metrics", metrics::metrics, Some(operations::metrics)),
+        Endpoint::get("/healthz", health::healthz, Some(operations::healthz)),
+        Endpoint::get("/readyz", health::readyz, Some(operations::readyz)),
+        Endpoint::get("/assets", health::assets, Some(operations::assets)),
+        Endpoint::get("/jobs/:id", jobs::status, Some(operations::job_status)),
+        Endpoint::get("/jobs/:id/result", jobs::result, Some(operations::job_result)),
+        Endpoint::get("/jobs/:id/events", jobs::events, Some(operations::job_events)),
+    ]
+}
+
+/// mounts every effect it is registered with as `POST /{name}`, handled by [`wrapper::handle`],
+/// and `POST /jobs/{name}`, handled by [`jobs::submit`]
+pub struct Routes(pub Router<Body>);
+
+impl Register for Routes {
+    fn register<E: Effect>(self, effect: E) -> Self {
+        let effect = Arc::new(effect);
+        let job = Arc::clone(&effect);
+
+        Self(self.0
+            .route(
+                &format!("/{}", E::NAME),
+                post(
+                    move |query: QueryResult, headers: HeaderMap, multipart: MultipartResult|
+                        wrapper::handle(Arc::clone(&effect), query, headers, multipart)
+                ),
+            )
+            .route(
+                &format!("/jobs/{}", E::NAME),
+                post(
+                    move |query: QueryResult, headers: HeaderMap, multipart: MultipartResult|
+                        jobs::submit(Arc::clone(&job), query, headers, multipart)
+                ),
+            )
+        )
+    }
+}
+
+/// mounts the routes of every effect and of the [`table`] onto `router`
+pub fn mount(router: Router<Body>) -> Router<Body> {
+    table()
+        .into_iter()
+        .fold(
+            effects::register(Routes(router)).0,
+            |router, endpoint| router.route(endpoint.path, endpoint.handler),
+        )
+}
+
+/// handler for "/effects", listing every effect with the schema of its options,
+/// every one of them unavailable until the assets have been warmed up
+#[allow(clippy::unused_async)]
+pub async fn list() -> Json<Vec<EffectInfo>> {
+    let config = &settings::get().effects;
+
+    Json(match health::loaded() {
+        Some(assets) => REGISTRY.info(assets, config),
+        None => REGISTRY.iter()
+            .map(|effect| EffectInfo {
+                available: false,
+                ..effect.info(config)
+            })
+            .collect(),
+    })
+}
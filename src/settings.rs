@@ -0,0 +1,341 @@
+//! module containing the runtime [`Config`] of the server,
+//! loaded from a TOML file with overrides from environment variables.
+//!
+//! the `[effects]` table is the [`EffectsConfig`] of the library, passed to every effect
+
+use std::{
+    fmt,
+    fs,
+    io,
+    net::{IpAddr, Ipv4Addr},
+    collections::HashMap,
+    path::PathBuf,
+    str::FromStr,
+    sync::OnceLock,
+    time::Duration,
+};
+use serde::Deserialize;
+
+use image_web::{config::{self, EffectsConfig}, effects::REGISTRY};
+
+/// the config, set once at startup by [`init`]
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// the config of the whole server
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub limits: LimitsConfig,
+    pub runtime: RuntimeConfig,
+    pub pool: PoolConfig,
+    pub jobs: JobsConfig,
+    pub cache: CacheConfig,
+    pub effects: EffectsConfig,
+}
+
+/// where the server listens and what it serves
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// address to bind to, `HOST`
+    pub host: IpAddr,
+    /// port to bind to, `PORT`
+    pub port: u16,
+    /// directory containing the effect assets (fonts, lego brick, minecraft blocks), `ASSETS_DIR`.
+    /// defaults to the copy built into the executable with the `embed` feature, to `./assets` otherwise
+    pub assets: Option<PathBuf>,
+    /// directory containing the static frontend files, `FRONTEND_DIR`.
+    /// defaults to the copy built into the executable with the `embed` feature, to `./frontend` otherwise
+    pub frontend: Option<PathBuf>,
+    /// refuse to start if any asset fails to load,
+    /// instead of only disabling the effects using it, `REQUIRE_ASSETS`
+    pub require_assets: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 8080,
+            assets: None,
+            frontend: None,
+            require_assets: false,
+        }
+    }
+}
+
+/// limits on the uploads and requests
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// maximum size of an upload in bytes, `MAX_UPLOAD_BYTES`
+    pub max_upload_bytes: usize,
+    /// maximum amount of pixels in a single frame, `MAX_PIXELS`
+    pub max_pixels: u64,
+    /// maximum amount of frames of an animated image, `MAX_FRAMES`
+    pub max_frames: usize,
+    /// maximum amount of pixels summed over every frame, `MAX_TOTAL_PIXELS`
+    pub max_total_pixels: u64,
+    /// maximum amount of steps in a pipeline
+    pub max_pipeline_steps: usize,
+    /// seconds an effect may take before answering 504, 0 disables it, `TIMEOUT`
+    pub timeout: u64,
+    /// timeouts overriding `timeout` for some effects (or the `pipeline`), keyed by name
+    pub effect_timeouts: HashMap<String, u64>,
+}
+
+impl LimitsConfig {
+    /// the timeout of the effect (or the `pipeline`) named `name`, if any
+    pub fn timeout_for(&self, name: &str) -> Option<Duration> {
+        let seconds = self.effect_timeouts
+            .get(name)
+            .copied()
+            .unwrap_or(self.timeout);
+
+        (seconds > 0).then(|| Duration::from_secs(seconds))
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_upload_bytes: 15_000_000,
+            max_pixels: 25_000_000,
+            max_frames: 150,
+            max_total_pixels: 40_000_000,
+            max_pipeline_steps: 10,
+            timeout: 60,
+            effect_timeouts: HashMap::new(),
+        }
+    }
+}
+
+/// threads of the async runtime, defaults to the ones of `tokio`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeConfig {
+    /// threads handling requests, `WORKER_THREADS`
+    pub worker_threads: Option<usize>,
+    /// maximum threads running blocking image processing, `BLOCKING_THREADS`
+    pub blocking_threads: Option<usize>,
+}
+
+/// the pool of workers processing images on the server
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PoolConfig {
+    /// amount of images processed at once, defaults to the amount of cpus, `WORKERS`
+    pub workers: usize,
+    /// amount of requests that may wait for a worker before being refused, `QUEUE_SIZE`
+    pub queue: usize,
+    /// seconds refused clients are told to wait in the `Retry-After` header
+    pub retry_after: u64,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            workers: std::thread::available_parallelism()
+                .map_or(4, usize::from),
+            queue: 64,
+            retry_after: 5,
+        }
+    }
+}
+
+/// the asynchronous jobs of the server
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobsConfig {
+    /// seconds the result of a job is kept once it finished, `JOB_TTL`
+    pub ttl: u64,
+    /// maximum amount of jobs stored at once, running or finished
+    pub capacity: usize,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self {
+            ttl: 600,
+            capacity: 256,
+        }
+    }
+}
+
+/// the cache of images processed by the server
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// whether processed images are cached at all, `CACHE_ENABLED`
+    pub enabled: bool,
+    /// maximum total size of the images cached in memory, in bytes
+    pub memory_budget: usize,
+    /// directory of the disk tier, disabled when not set, `CACHE_DIR`
+    pub disk: Option<PathBuf>,
+    /// maximum total size of the images cached on disk, in bytes
+    pub disk_budget: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            memory_budget: 64_000_000,
+            disk: None,
+            disk_budget: 1_000_000_000,
+        }
+    }
+}
+
+/// errors that can occur while loading the config
+#[derive(Debug)]
+pub enum ConfigError {
+    /// the config file could not be read
+    Io(PathBuf, io::Error),
+    /// the config file is not valid TOML or has unknown or mistyped keys
+    Parse(PathBuf, toml::de::Error),
+    /// an environment variable override could not be parsed
+    Env(&'static str, String),
+    /// a value is out of its valid range
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, err) => write!(f, "failed to read {}: {err}", path.display()),
+            Self::Parse(path, err) => write!(f, "failed to parse {}: {err}", path.display()),
+            Self::Env(var, value) => write!(f, "invalid value for environment variable {var}: {value:?}"),
+            Self::Invalid(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// reads an environment variable into `target` if it is set
+fn env_override<T: FromStr>(var: &'static str, target: &mut T) -> Result<(), ConfigError> {
+    if let Ok(value) = std::env::var(var) {
+        *target = value.parse()
+            .map_err(|_| ConfigError::Env(var, value))?;
+    }
+
+    Ok(())
+}
+
+impl Config {
+    /// loads the config from the TOML file at `CONFIG_PATH` (or `./config.toml` if it exists),
+    /// applies the environment variable overrides and validates the result
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut config = match config::path() {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// parses the config from a TOML file
+    fn from_file(path: PathBuf) -> Result<Self, ConfigError> {
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) => return Err(ConfigError::Io(path, err)),
+        };
+
+        toml::from_str(&contents)
+            .map_err(|err| ConfigError::Parse(path, err))
+    }
+
+    /// applies the overrides from environment variables
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        env_override("HOST", &mut self.server.host)?;
+        env_override("PORT", &mut self.server.port)?;
+        env_override("REQUIRE_ASSETS", &mut self.server.require_assets)?;
+
+        env_override("MAX_UPLOAD_BYTES", &mut self.limits.max_upload_bytes)?;
+        env_override("MAX_PIXELS", &mut self.limits.max_pixels)?;
+        env_override("MAX_FRAMES", &mut self.limits.max_frames)?;
+        env_override("MAX_TOTAL_PIXELS", &mut self.limits.max_total_pixels)?;
+        env_override("TIMEOUT", &mut self.limits.timeout)?;
+
+        env_override("WORKERS", &mut self.pool.workers)?;
+        env_override("QUEUE_SIZE", &mut self.pool.queue)?;
+        env_override("JOB_TTL", &mut self.jobs.ttl)?;
+        env_override("CACHE_ENABLED", &mut self.cache.enabled)?;
+
+        if let Ok(dir) = std::env::var("ASSETS_DIR") {
+            self.server.assets = Some(PathBuf::from(dir));
+        }
+        if let Ok(dir) = std::env::var("FRONTEND_DIR") {
+            self.server.frontend = Some(PathBuf::from(dir));
+        }
+        if let Ok(dir) = std::env::var("CACHE_DIR") {
+            self.cache.disk = Some(PathBuf::from(dir));
+        }
+
+        if let Ok(value) = std::env::var("WORKER_THREADS") {
+            self.runtime.worker_threads = Some(
+                value.parse()
+                    .map_err(|_| ConfigError::Env("WORKER_THREADS", value))?
+            );
+        }
+        if let Ok(value) = std::env::var("BLOCKING_THREADS") {
+            self.runtime.blocking_threads = Some(
+                value.parse()
+                    .map_err(|_| ConfigError::Env("BLOCKING_THREADS", value))?
+            );
+        }
+
+        Ok(())
+    }
+
+    /// checks that every value is within its valid range
+    fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = self.effects.validate();
+
+        let positive = [
+            ("limits.max_upload_bytes", self.limits.max_upload_bytes as u64),
+            ("limits.max_pixels", self.limits.max_pixels),
+            ("limits.max_frames", self.limits.max_frames as u64),
+            ("limits.max_total_pixels", self.limits.max_total_pixels),
+            ("limits.max_pipeline_steps", self.limits.max_pipeline_steps as u64),
+            ("pool.workers", self.pool.workers as u64),
+            ("jobs.ttl", self.jobs.ttl),
+            ("jobs.capacity", self.jobs.capacity as u64),
+            ("runtime.worker_threads", self.runtime.worker_threads.map_or(1, |threads| threads as u64)),
+            ("runtime.blocking_threads", self.runtime.blocking_threads.map_or(1, |threads| threads as u64)),
+        ];
+
+        for (key, value) in positive {
+            if value == 0 {
+                errors.push(format!("{key} must be greater than 0"));
+            }
+        }
+
+        for name in self.limits.effect_timeouts.keys() {
+            if name != "pipeline" && REGISTRY.get(name).is_none() {
+                errors.push(format!("limits.effect_timeouts.{name} does not name an effect"));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(errors.join(", ")))
+        }
+    }
+}
+
+/// sets the config used by the whole server, should be called once at startup
+pub fn init(config: Config) {
+    CONFIG.set(config)
+        .expect("config is already initialized");
+}
+
+/// the config of the server, falling back to the defaults if [`init`] was never called
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
@@ -1,77 +1,548 @@
-//! module containing the the wrapper macro for routes on the webserver
-
-use std::fmt::Display;
-use axum::http::StatusCode;
-
-/// simple helper function to map unexpected errors
-/// into an axum response with `500 HTTP Status code` and displaying the error message
-pub fn map_err<E>(err: E) -> (StatusCode, String)
-where
-    E: Display
-{
-    (StatusCode::INTERNAL_SERVER_ERROR, format!("Something went wrong: {err}"))
+//! module containing the handler wrapping every effect route on the webserver
+//! and the helper functions it shares with the other processing routes
+
+use std::{collections::HashMap, sync::Arc};
+use axum::{
+    body::Bytes,
+    extract::{multipart::Field, Multipart, Query},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use image_web::{
+    config::EffectsConfig,
+    context::{Context, Stage},
+    effect::{self, Effect, Parameter},
+    error::{Error as ImageError, FieldError},
+    models::{FormatOption, FrameMode, FrameOption},
+    output::{Output, OutputFormat},
+};
+use ril::prelude::*;
+use serde_json::Value;
+
+use crate::{
+    cache::{self, CACHE},
+    health::{self, ASSETS},
+    metrics::{self, Stage as Timed},
+    pool::{self, POOL},
+    probe::{self, Probe},
+    response::{self, Error, MultipartResult, QueryResult, Result},
+    settings,
+};
+
+/// checks the decoded input against the frame count and total pixel limits
+/// before every frame is processed,
+/// failing with [`ImageError::TooLarge`] if either is exceeded
+pub fn check_frame_limits<P: Pixel>(
+    sequence: &ImageSequence<P>,
+    max_frames: usize,
+    max_pixels: u64,
+) -> Result<()> {
+    if sequence.len() > max_frames {
+        return Err(ImageError::TooLarge {
+            unit: "frames",
+            actual: sequence.len() as u64,
+            limit: max_frames as u64,
+        }.into());
+    }
+
+    let pixels = sequence.iter()
+        .map(|frame| {
+            let (width, height) = frame.image().dimensions();
+            u64::from(width) * u64::from(height)
+        })
+        .sum::<u64>();
+
+    if pixels > max_pixels {
+        return Err(ImageError::TooLarge {
+            unit: "pixels",
+            actual: pixels,
+            limit: max_pixels,
+        }.into());
+    }
+
+    Ok(())
+}
+
+/// checks the dimensions and frame count declared by the headers of the upload
+/// against the pixel and frame limits, before any of it is decoded,
+/// failing with [`ImageError::TooLarge`] if any is exceeded
+pub fn check_probe(probe: &Probe) -> Result<()> {
+    let limits = &settings::get().limits;
+
+    if probe.pixels() > limits.max_pixels {
+        return Err(ImageError::TooLarge {
+            unit: "pixels",
+            actual: probe.pixels(),
+            limit: limits.max_pixels,
+        }.into());
+    }
+
+    if probe.frames > limits.max_frames as u64 {
+        return Err(ImageError::TooLarge {
+            unit: "frames",
+            actual: probe.frames,
+            limit: limits.max_frames as u64,
+        }.into());
+    }
+
+    if probe.total_pixels() > limits.max_total_pixels {
+        return Err(ImageError::TooLarge {
+            unit: "pixels",
+            actual: probe.total_pixels(),
+            limit: limits.max_total_pixels,
+        }.into());
+    }
+
+    Ok(())
+}
+
+/// names of the options shared by every processing route, see [`common_options`]
+pub const COMMON_FIELDS: [&str; 3] = ["format", "quality", "frames"];
+
+/// parses the [`COMMON_FIELDS`] shared by every processing route
+/// from the query string and the `form` fields of the upload, before any processing happens.
+/// a field given in the form takes precedence over the query string.
+///
+/// fails with [`ImageError::InvalidFields`] listing every one of them that is invalid
+pub fn common_options(
+    query: &HashMap<String, String>,
+    form: &HashMap<String, String>,
+) -> Result<(FormatOption, FrameOption)> {
+    let mut fields = query.clone();
+    fields.extend(
+        form.iter()
+            .filter(|(name, _)| COMMON_FIELDS.contains(&name.as_str()))
+            .map(|(name, value)| (name.clone(), value.clone()))
+    );
+    let mut errors = Vec::new();
+
+    let format = fields.get("format").and_then(|raw| {
+        let format = serde_json::from_value::<OutputFormat>(Value::String(raw.to_ascii_lowercase()))
+            .ok()
+            .filter(|format| format.is_enabled());
+        if format.is_none() {
+            errors.push(FieldError {
+                field: "format".to_string(),
+                message: format!(
+                    "must be one of {}",
+                    OutputFormat::enabled()
+                        .map(OutputFormat::extension)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
+                minimum: None,
+                maximum: None,
+            });
+        }
+        format
+    });
+
+    let quality = fields.get("quality").and_then(|raw| {
+        Parameter::optional_integer("quality", "Quality for lossy formats", 1..=100)
+            .parse(raw)
+            .map_err(|err| errors.push(err))
+            .ok()
+            .and_then(|quality| quality.as_u64())
+            .and_then(|quality| u8::try_from(quality).ok())
+    });
+
+    let frames = fields.get("frames").and_then(|raw| {
+        let frames = serde_json::from_value::<FrameMode>(Value::String(raw.to_ascii_lowercase())).ok();
+        if frames.is_none() {
+            errors.push(FieldError {
+                field: "frames".to_string(),
+                message: "must be all or first".to_string(),
+                minimum: None,
+                maximum: None,
+            });
+        }
+        frames
+    });
+
+    if errors.is_empty() {
+        Ok((FormatOption { format, quality }, FrameOption { frames }))
+    } else {
+        Err(ImageError::InvalidFields(errors).into())
+    }
+}
+
+/// extracts the `Accept` header so it can be moved into the blocking task
+pub fn accept_header(headers: &HeaderMap) -> Option<String> {
+    headers.get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// reads the bytes of a multipart field,
+/// failing with [`ImageError::TooLarge`] once they exceed `limits.max_upload_bytes`
+pub async fn read_field(mut field: Field<'_>) -> Result<Vec<u8>> {
+    let limit = settings::get().limits.max_upload_bytes;
+    let mut size = 0;
+    let mut buffer = Vec::<u8>::new();
+
+    while let Some(chunk) = field.chunk().await.map_err(response::multipart)? {
+        size += chunk.len();
+
+        if size > limit {
+            return Err(ImageError::TooLarge {
+                unit: "bytes",
+                actual: size as u64,
+                limit: limit as u64,
+            }.into());
+        }
+
+        buffer.extend_from_slice(&chunk);
+    }
+
+    Ok(buffer)
+}
+
+/// decodes the uploaded bytes into every frame of the image,
+/// probing their headers against the limits first (see [`check_probe`])
+/// and checking the decoded frames again if every frame is going to be processed.
+///
+/// fails with [`ImageError::UnsupportedFormat`] if the format of the bytes cannot be recognized
+/// and [`ImageError::CorruptImage`] if they cannot be decoded.
+/// returns the frames alongside whether or not each of them should be processed
+pub fn decode(buffer: &[u8], frames: &FrameOption) -> Result<(ImageSequence<Rgba>, bool)> {
+    let encoding = ImageFormat::infer_encoding(buffer);
+    if matches!(encoding, ImageFormat::Unknown) {
+        return Err(ImageError::UnsupportedFormat.into());
+    }
+
+    if let Some(probe) = probe::probe(buffer)
+        .map_err(|err| ImageError::CorruptImage(err.to_string()))?
+    {
+        check_probe(&probe)?;
+    }
+
+    let sequence = ImageSequence::<Rgba>::from_bytes_inferred(buffer)
+        .and_then(|sequence| sequence.into_sequence())
+        .map_err(|err| ImageError::CorruptImage(err.to_string()))?;
+
+    if let Some(frame) = sequence.iter().next() {
+        let (width, height) = frame.image().dimensions();
+        tracing::info!(
+            format = ?encoding,
+            width,
+            height,
+            frames = sequence.len(),
+            bytes = buffer.len(),
+            "decoded upload",
+        );
+        metrics::observe_input(buffer.len(), (width, height));
+    }
+
+    let per_frame = frames.frames != Some(FrameMode::First);
+    if per_frame {
+        let limits = &settings::get().limits;
+        check_frame_limits(&sequence, limits.max_frames, limits.max_total_pixels)?;
+    }
+
+    Ok((sequence, per_frame))
+}
+
+/// negotiates the output format and encodes the output into it,
+/// failing with [`Error::NotAcceptable`] if no requested format can represent the output
+pub fn encode(
+    output: Output,
+    format: &FormatOption,
+    accept: Option<&str>,
+) -> Result<(OutputFormat, Vec<u8>)> {
+    let animated = output.is_animated();
+    let output_format = OutputFormat::negotiate(format.format, accept, animated)
+        .ok_or(Error::NotAcceptable { animated })?;
+
+    let bytes = output.encode(output_format, format.quality)?;
+    Ok((output_format, bytes))
 }
 
-/// a macro for boilerplate wrapper code around
-/// the actual image processing functionality for that endpoint
+/// name of the multipart part holding the options as a JSON object
+pub const OPTIONS_FIELD: &str = "options";
+
+/// a multipart upload to an effect route, see [`read_upload`]
+pub struct Upload {
+    /// the image bytes
+    pub image: Vec<u8>,
+    /// the part named [`OPTIONS_FIELD`], holding the options as JSON
+    pub options: Option<Value>,
+    /// the options and [`COMMON_FIELDS`] given as their own form fields
+    pub fields: HashMap<String, String>,
+}
+
+/// reads the text of a form field into `fields`, under its name.
 ///
-/// it takes in `function` assumed to be a function and then
-/// a struct to deserialize optional query arguments into.
-/// Since macros cannot have optional arguments, simply use [`models::NoArgs`] to represent no arguments
+/// fails with [`ImageError::InvalidFields`] if the field was already given
+pub async fn read_form_field(field: Field<'_>, fields: &mut HashMap<String, String>) -> Result<()> {
+    let name = field.name().unwrap_or_default().to_string();
+    let bytes = read_field(field).await?;
+    let value = String::from_utf8(bytes)
+        .map_err(|_| Error::BadRequest(format!("the form field {name} is not valid UTF-8")))?;
+
+    if fields.contains_key(&name) {
+        return Err(ImageError::InvalidFields(vec![FieldError {
+            field: name,
+            message: "is given more than once in the form".to_string(),
+            minimum: None,
+            maximum: None,
+        }]).into());
+    }
+    fields.insert(name, value);
+
+    Ok(())
+}
+
+/// reads a multipart upload to an effect whose options are `parameters`.
 ///
-#[macro_export]
-macro_rules! wrap_fn {
-    ( $function:expr, $query:ty ) => {
-        |axum::extract::Query(query): axum::extract::Query<$query>,
-        mut multipart: axum::extract::Multipart|
-            async move {
-                if let Some(mut field) = multipart.next_field()
-                    .await
-                    .map_err(wrapper::map_err)?
-                {
-                    let mut size = 0;
-                    let mut buffer = Vec::<u8>::new();
-
-                    while let Some(chunk) = field.chunk()
-                        .await
-                        .map_err(wrapper::map_err)?
-                    {
-                        size += chunk.len();
-
-                        if size > MAX_IMAGE_SIZE {
-                            return Err((
-                                StatusCode::PAYLOAD_TOO_LARGE,
-                                format!("The size of the image provided is {} bytes which exceeds the limit of {} bytes", size, MAX_IMAGE_SIZE)
-                            ));
-                        }
-
-                        buffer.extend_from_slice(&chunk);
-                    }
-
-                    let bytes = tokio::task::spawn_blocking(
-                        move || -> ril::Result<Vec<u8>> {
-                            let image = ImageSequence::<Rgba>::from_bytes_inferred(&*buffer)?
-                                .into_sequence()?
-                                .into_first_image();
-
-                            let image = $function(image, query)?;
-                            let mut bytes = Vec::<u8>::new();
-                            image.encode(ImageFormat::Png, &mut bytes)?;
-
-                            Ok(bytes)
-                        }
-                    )
-                        .await
-                        .map_err(wrapper::map_err)?
-                        .map_err(wrapper::map_err)?;
-
-                    Ok((axum::TypedHeader(axum::headers::ContentType::png()), bytes))
-                } else {
-                    Err((
-                        StatusCode::BAD_REQUEST,
-                        "Missing required multipart field for image bytes".to_string(),
-                    ))
-                }
+/// the part named [`OPTIONS_FIELD`] holds the options as a JSON object,
+/// the parts named after an option or one of the [`COMMON_FIELDS`] hold its value,
+/// and the first of the other parts (or any part with a file name) holds the image.
+/// a second image fails with [`Error::BadRequest`],
+/// an option given twice as a form field with [`ImageError::InvalidFields`]
+pub async fn read_upload(multipart: &mut Multipart, parameters: &[Parameter]) -> Result<Upload> {
+    let mut image = None;
+    let mut options = None;
+    let mut fields = HashMap::new();
+
+    while let Some(field) = multipart.next_field().await.map_err(response::multipart)? {
+        let name = field.name().unwrap_or_default().to_string();
+        let file = field.file_name().is_some();
+
+        if !file && name == OPTIONS_FIELD {
+            if options.is_some() {
+                return Err(Error::BadRequest(format!("the {OPTIONS_FIELD} part is given more than once")));
             }
+
+            let bytes = read_field(field).await?;
+            options = Some(
+                serde_json::from_slice::<Value>(&bytes)
+                    .map_err(|err| ImageError::InvalidOption(format!("the {OPTIONS_FIELD} part is not valid JSON: {err}")))?
+            );
+        } else if !file && (
+            COMMON_FIELDS.contains(&name.as_str())
+            || parameters.iter().any(|parameter| parameter.name == name)
+        ) {
+            read_form_field(field, &mut fields).await?;
+        } else if image.is_none() {
+            image = Some(read_field(field).await?);
+        } else {
+            return Err(Error::BadRequest(format!("unexpected multipart field {name}, only one image can be uploaded")));
+        }
+    }
+
+    Ok(Upload {
+        image: image.ok_or(Error::MissingField("image bytes"))?,
+        options,
+        fields,
+    })
+}
+
+/// the options of `E`, merged from the `query` string and the form of the `upload`
+/// and validated against its [`parameters`](Effect::parameters) with `config` before any processing happens.
+///
+/// options given in the form, either in the [`OPTIONS_FIELD`] part or as their own fields,
+/// take precedence over the query string.
+/// an option given both in the [`OPTIONS_FIELD`] part and as its own field fails with [`ImageError::InvalidFields`],
+/// as does every option of the wrong type or out of its range.
+/// the [`COMMON_FIELDS`] are not options of the effect, so giving one in the [`OPTIONS_FIELD`] part
+/// fails with [`ImageError::InvalidOption`] rather than being ignored
+pub fn options<E: Effect>(
+    query: &HashMap<String, String>,
+    upload: &Upload,
+    config: &EffectsConfig,
+) -> Result<E::Options> {
+    let parameters = E::parameters(config);
+    let mut options = effect::parse_fields(&parameters, query)?;
+    let form = effect::parse_fields(&parameters, &upload.fields)?;
+
+    if let Some(part) = &upload.options {
+        let part = part.as_object()
+            .ok_or_else(|| ImageError::InvalidOption(format!("the {OPTIONS_FIELD} part must be a JSON object")))?;
+
+        if let Some(common) = COMMON_FIELDS.into_iter().find(|name| part.contains_key(*name)) {
+            return Err(ImageError::InvalidOption(format!(
+                "{common} cannot be given in the {OPTIONS_FIELD} part, give it in the query string or as its own form field",
+            )).into());
+        }
+
+        let conflicts = form.keys()
+            .filter(|name| part.contains_key(*name))
+            .map(|name| FieldError {
+                field: name.clone(),
+                message: format!("is given both in the {OPTIONS_FIELD} part and as a form field"),
+                minimum: None,
+                maximum: None,
+            })
+            .collect::<Vec<_>>();
+        if !conflicts.is_empty() {
+            return Err(ImageError::InvalidFields(conflicts).into());
+        }
+
+        options.extend(part.clone());
+    }
+    options.extend(form);
+
+    E::parse_options(Value::Object(options), config)
+        .map_err(Error::from)
+}
+
+/// decodes the upload, applies `effect` to it with the configured `effects` and encodes the output,
+/// the blocking part of every effect route and job.
+///
+/// runs in a span recording the effect and its options,
+/// in which the input and the time spent in each stage are logged
+pub fn process<E: Effect>(
+    effect: &E,
+    options: &E::Options,
+    buffer: &[u8],
+    frames: &FrameOption,
+    format: &FormatOption,
+    accept: Option<&str>,
+    context: &Context,
+) -> Result<(OutputFormat, Vec<u8>)> {
+    let _span = tracing::info_span!(
+        "process",
+        effect = E::NAME,
+        options = %serde_json::to_string(options).unwrap_or_default(),
+    ).entered();
+
+    context.check()?;
+    context.set_stage(Stage::Decoding);
+    let timer = metrics::time_stage(E::NAME, Timed::Decode);
+    let (sequence, per_frame) = decode(buffer, frames)?;
+    drop(timer);
+
+    context.set_stage(Stage::Processing);
+    let timer = metrics::time_stage(E::NAME, Timed::Effect);
+    let count = if per_frame { u32::try_from(sequence.len()).unwrap_or(u32::MAX) } else { 1 };
+    let mut index = 0;
+    let output = Output::map_frames(
+        sequence,
+        per_frame,
+        context.max_pixels(),
+        |image| {
+            context.set_frame(index, count);
+            index += 1;
+            effect.apply(image, options.clone(), &ASSETS, &settings::get().effects, context)
+        },
+    )?;
+    drop(timer);
+
+    context.set_stage(Stage::Encoding);
+    let _timer = metrics::time_stage(E::NAME, Timed::Encode);
+    encode(output, format, accept)
+}
+
+/// the [`CACHE`] key of an effect route or job,
+/// `None` if the cache is disabled or the options are not [`cacheable`](Effect::cacheable).
+///
+/// hashes the whole upload, so it should not be called on the async runtime
+pub fn cache_key<E: Effect>(
+    buffer: &[u8],
+    options: &E::Options,
+    format: &FormatOption,
+    frames: &FrameOption,
+    accept: Option<&str>,
+) -> Option<String> {
+    (CACHE.enabled() && E::cacheable(options))
+        .then(|| cache::key(E::NAME, buffer, options, &settings::get().effects, format, frames, accept))
+}
+
+/// the handler wrapping every [`Effect`] route,
+/// boilerplate around the actual image processing functionality for that endpoint
+///
+/// the options are read from the query string and the form of the upload, see [`options`].
+/// animated input images have every frame processed unless `?frames=first` is given,
+/// see [`Output::map_frames`].
+/// the effect may return either a single image or an animated sequence,
+/// which is then encoded in the format picked by [`OutputFormat::negotiate`]
+/// from the `format` option and `Accept` header.
+/// decoding, processing and encoding all run as one job on the [`POOL`],
+/// stopped once the client disconnects or the timeout of the effect passes.
+///
+/// outputs of [`cacheable`](Effect::cacheable) options are stored in the [`CACHE`]
+/// and tagged with their cache key as the `ETag`, answering `304 Not Modified` to a matching `If-None-Match`
+pub async fn handle<E: Effect>(
+    effect: Arc<E>,
+    query: QueryResult,
+    headers: HeaderMap,
+    multipart: MultipartResult,
+) -> Result<Response> {
+    let Query(query) = query.map_err(response::query)?;
+    let mut multipart = multipart.map_err(response::upload)?;
+    let config = &settings::get().effects;
+    health::check(E::ASSETS)?;
+    let accept = accept_header(&headers);
+    let upload = read_upload(&mut multipart, &E::parameters(config)).await?;
+    let (format, frames) = common_options(&query, &upload.fields)?;
+    let options = options::<E>(&query, &upload, config)?;
+    let buffer = Bytes::from(upload.image);
+
+    let mut key = None;
+    if CACHE.enabled() && E::cacheable(&options) {
+        let lookup = (buffer.clone(), options.clone(), format.clone(), frames.clone(), accept.clone(), headers);
+
+        // hashing the upload and reading the disk tier both block, so they run off the async runtime
+        let (found, not_modified, cached) = tokio::task::spawn_blocking(move || {
+            let (buffer, options, format, frames, accept, headers) = lookup;
+            let key = cache::key(E::NAME, &buffer, &options, &settings::get().effects, &format, &frames, accept.as_deref());
+            let not_modified = cache::if_none_match(&headers, &cache::etag(&key));
+            let cached = if not_modified { None } else { CACHE.get(&key) };
+
+            (key, not_modified, cached)
+        })
+            .await
+            .map_err(response::join)?;
+
+        let etag = cache::etag(&found);
+        if not_modified {
+            return Ok((
+                StatusCode::NOT_MODIFIED,
+                [(header::ETAG, etag), (header::VARY, "Accept".to_string())],
+            ).into_response());
+        }
+        if let Some((output_format, bytes)) = cached {
+            return Ok(respond(output_format, bytes, Some(etag)));
+        }
+
+        key = Some(found);
+    }
+
+    let context = Context::new()
+        .with_max_pixels(settings::get().limits.max_total_pixels);
+    let _cancel = context.cancel_on_drop();
+    let job = context.clone();
+    let cached = key.clone();
+
+    let timeout = settings::get().limits.timeout_for(E::NAME);
+    let (output_format, bytes) = pool::with_timeout(&context, timeout, POOL.run(move || {
+        let (output_format, bytes) = process(&*effect, &options, &buffer, &frames, &format, accept.as_deref(), &job)?;
+        let bytes = Bytes::from(bytes);
+
+        if let Some(key) = cached {
+            CACHE.insert(key, output_format, bytes.clone());
+        }
+        Ok((output_format, bytes))
+    }))
+        .await?;
+
+    Ok(respond(output_format, bytes, key.as_deref().map(cache::etag)))
+}
+
+/// the response carrying a processed image, tagged with its `ETag` if it was cached
+fn respond(output_format: OutputFormat, bytes: Bytes, etag: Option<String>) -> Response {
+    let mut response = (
+        [
+            (header::CONTENT_TYPE, output_format.mime_type()),
+            (header::VARY, "Accept"),
+        ],
+        bytes,
+    ).into_response();
+
+    if let Some(etag) = etag.and_then(|etag| HeaderValue::from_str(&etag).ok()) {
+        response.headers_mut()
+            .insert(header::ETAG, etag);
     }
-}
\ No newline at end of file
+
+    response
+}